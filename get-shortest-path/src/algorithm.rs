@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// Search used for each leg of a route.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Dijkstra,
+    /// Dijkstra guided by a great-circle estimate of the distance left to the destination.
+    #[serde(rename = "astar")]
+    AStar,
+    /// Dijkstra grown from both ends at once, meeting in the middle.
+    Bidirectional,
+    /// Query over a preprocessed contraction hierarchy (see `build_ch`).
+    #[serde(rename = "ch")]
+    ContractionHierarchy,
+    /// A* with landmark distance tables as the heuristic (see `build_alt`).
+    Alt,
+}
@@ -0,0 +1,263 @@
+use crate::ch::Entry;
+use crate::dijkstra::edge_weight;
+use crate::graph::Graph;
+use crate::mode::TravelMode;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+
+/// Landmark distance tables for one travel mode (ALT: A*, Landmarks, Triangle inequality).
+///
+/// For every landmark L the tables hold d(L, v) and d(v, L) for all nodes v. By the
+/// triangle inequality d(L, t) - d(L, v) and d(v, L) - d(t, L) are both lower bounds
+/// on d(v, t), which makes a much tighter A* heuristic than straight-line distance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LandmarkTables {
+    mode: TravelMode,
+    node_ids: Vec<i64>,
+    landmarks: Vec<i64>,
+    // from_landmark[l][v] = d(landmark l, v), infinite when unreachable
+    from_landmark: Vec<Vec<f64>>,
+    // to_landmark[l][v] = d(v, landmark l)
+    to_landmark: Vec<Vec<f64>>,
+    #[serde(skip)]
+    index: HashMap<i64, u32>,
+}
+
+// Out-arcs (or in-arcs when reversed) of every node by index, weighted like the search
+fn index_arcs(graph: &Graph, mode: TravelMode, index: &HashMap<i64, u32>, reverse: bool) -> Vec<Vec<(u32, f64)>> {
+    let mut arcs = vec![Vec::new(); index.len()];
+    for node in graph.nodes() {
+        for next_id in node.adjacency(mode) {
+            let (Some(&from), Some(&to), Some(next_node)) = (index.get(&node.id), index.get(next_id), graph.get_node(*next_id)) else {
+                continue;
+            };
+            let weight = edge_weight(mode, node, next_node);
+            if reverse {
+                arcs[to as usize].push((from, weight));
+            } else {
+                arcs[from as usize].push((to, weight));
+            }
+        }
+    }
+    arcs
+}
+
+// Full one-to-all Dijkstra over index arcs
+fn one_to_all(arcs: &[Vec<(u32, f64)>], start: u32) -> Vec<f64> {
+    let mut distances = vec![f64::INFINITY; arcs.len()];
+    let mut heap = BinaryHeap::new();
+
+    distances[start as usize] = 0.0;
+    heap.push(Entry { cost: 0.0, node: start });
+
+    while let Some(Entry { cost, node }) = heap.pop() {
+        if cost > distances[node as usize] {
+            continue;
+        }
+        for &(next, weight) in &arcs[node as usize] {
+            let next_cost = cost + weight;
+            if next_cost < distances[next as usize] {
+                distances[next as usize] = next_cost;
+                heap.push(Entry { cost: next_cost, node: next });
+            }
+        }
+    }
+
+    distances
+}
+
+impl LandmarkTables {
+    /// Pick `landmark_count` landmarks by farthest selection and compute their tables.
+    /// Each new landmark is the reachable node farthest from all landmarks chosen so far,
+    /// which spreads them over the edge of the map where they give the best bounds.
+    pub fn build(graph: &Graph, mode: TravelMode, landmark_count: usize) -> LandmarkTables {
+        let mut node_ids: Vec<i64> = graph.nodes().map(|node| node.id).collect();
+        node_ids.sort_unstable();
+        let index: HashMap<i64, u32> = node_ids.iter().enumerate().map(|(i, id)| (*id, i as u32)).collect();
+
+        let forward_arcs = index_arcs(graph, mode, &index, false);
+        let backward_arcs = index_arcs(graph, mode, &index, true);
+
+        let mut tables = LandmarkTables {
+            mode,
+            node_ids,
+            landmarks: Vec::new(),
+            from_landmark: Vec::new(),
+            to_landmark: Vec::new(),
+            index,
+        };
+        if tables.node_ids.is_empty() {
+            return tables;
+        }
+
+        // Start from the node farthest from an arbitrary one, then keep maximizing
+        // the distance to the closest landmark already chosen
+        let seed_distances = one_to_all(&forward_arcs, 0);
+        let mut closest_landmark = seed_distances;
+        while tables.landmarks.len() < landmark_count {
+            let farthest = closest_landmark
+                .iter()
+                .enumerate()
+                .filter(|(_, distance)| distance.is_finite() && **distance > 0.0)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(node, _)| node as u32);
+            let Some(landmark) = farthest else {
+                break; // Every reachable node already is a landmark
+            };
+
+            let from_landmark = one_to_all(&forward_arcs, landmark);
+            let to_landmark = one_to_all(&backward_arcs, landmark);
+            for (closest, distance) in closest_landmark.iter_mut().zip(&from_landmark) {
+                if distance.is_finite() || closest.is_finite() {
+                    *closest = closest.min(*distance);
+                }
+            }
+
+            tables.landmarks.push(tables.node_ids[landmark as usize]);
+            tables.from_landmark.push(from_landmark);
+            tables.to_landmark.push(to_landmark);
+        }
+
+        tables
+    }
+
+    pub fn mode(&self) -> TravelMode {
+        self.mode
+    }
+
+    pub fn landmarks(&self) -> &[i64] {
+        &self.landmarks
+    }
+
+    /// Number of stored distances across both tables.
+    pub fn entry_count(&self) -> usize {
+        self.from_landmark.iter().chain(self.to_landmark.iter()).map(|table| table.len()).sum()
+    }
+
+    /// Size of the distance tables in bytes.
+    pub fn table_bytes(&self) -> usize {
+        self.entry_count() * std::mem::size_of::<f64>()
+    }
+
+    /// Lower bound on the distance from `from_id` to `to_id`, 0 when nothing is known.
+    pub fn lower_bound(&self, from_id: i64, to_id: i64) -> f64 {
+        let (Some(&from), Some(&to)) = (self.index.get(&from_id), self.index.get(&to_id)) else {
+            return 0.0;
+        };
+        let (from, to) = (from as usize, to as usize);
+
+        let mut bound: f64 = 0.0;
+        for (from_landmark, to_landmark) in self.from_landmark.iter().zip(&self.to_landmark) {
+            // Unreachable entries say nothing finite, skip them
+            let (landmark_to_target, landmark_to_node) = (from_landmark[to], from_landmark[from]);
+            if landmark_to_target.is_finite() && landmark_to_node.is_finite() {
+                bound = bound.max(landmark_to_target - landmark_to_node);
+            }
+            let (node_to_landmark, target_to_landmark) = (to_landmark[from], to_landmark[to]);
+            if node_to_landmark.is_finite() && target_to_landmark.is_finite() {
+                bound = bound.max(node_to_landmark - target_to_landmark);
+            }
+        }
+        bound
+    }
+}
+
+/// Write one set of landmark tables per mode to `path`.
+pub fn write_landmarks(path: &str, tables: &[LandmarkTables]) -> Result<(), io::Error> {
+    let writer = BufWriter::new(File::create(path)?);
+    bincode::serialize_into(writer, tables).map_err(io::Error::other)
+}
+
+/// Read the tables written by `write_landmarks`.
+pub fn read_landmarks(path: &str) -> Result<Vec<LandmarkTables>, io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut tables: Vec<LandmarkTables> = bincode::deserialize_from(reader).map_err(io::Error::other)?;
+    for table in &mut tables {
+        table.index = table.node_ids.iter().enumerate().map(|(i, id)| (*id, i as u32)).collect();
+    }
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra;
+    use crate::test_support::{princeton, sample_pairs};
+
+    const LANDMARKS: usize = 8;
+    // Number of node pairs compared against plain Dijkstra per mode
+    const PAIRS: usize = 200;
+
+    #[test]
+    fn landmarks_are_distinct_graph_nodes() {
+        let graph = princeton();
+        let tables = LandmarkTables::build(&graph, TravelMode::Foot, LANDMARKS);
+        let mut landmarks = tables.landmarks().to_vec();
+        assert_eq!(landmarks.len(), LANDMARKS);
+        assert!(landmarks.iter().all(|id| graph.get_node(*id).is_some()));
+        landmarks.sort_unstable();
+        landmarks.dedup();
+        assert_eq!(landmarks.len(), LANDMARKS);
+    }
+
+    // The bounds must stay below the true distance or ALT loses optimality,
+    // and ALT itself must find the distances plain Dijkstra finds
+    #[tokio::test]
+    async fn bounds_hold_and_alt_matches_dijkstra() {
+        let graph = princeton();
+        for mode in [TravelMode::Foot, TravelMode::Car, TravelMode::Bike] {
+            let tables = LandmarkTables::build(&graph, mode, LANDMARKS);
+            for (src_id, dest_id) in sample_pairs(&graph, mode, PAIRS) {
+                let src_node = graph.get_node(src_id).unwrap().clone();
+                let dest_node = graph.get_node(dest_id).unwrap().clone();
+
+                let expected = dijkstra::dijkstra(&graph, mode, src_node.clone(), dest_node.clone()).await;
+                let actual = dijkstra::alt(&graph, &tables, src_node, dest_node).await;
+                assert_eq!(actual.node_ids.is_empty(), expected.node_ids.is_empty(), "{:?} {} -> {}", mode, src_id, dest_id);
+                if expected.node_ids.is_empty() {
+                    continue;
+                }
+                let bound = tables.lower_bound(src_id, dest_id);
+                assert!(
+                    bound <= expected.distance + 1e-6,
+                    "{:?} {} -> {}: bound {:.3} m above distance {:.3} m",
+                    mode,
+                    src_id,
+                    dest_id,
+                    bound,
+                    expected.distance
+                );
+                assert!(
+                    (actual.distance - expected.distance).abs() < 1e-6,
+                    "{:?} {} -> {}: dijkstra {:.3} m, alt {:.3} m",
+                    mode,
+                    src_id,
+                    dest_id,
+                    expected.distance,
+                    actual.distance
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tables_survive_a_round_trip() {
+        let graph = princeton();
+        let tables = LandmarkTables::build(&graph, TravelMode::Bike, LANDMARKS);
+        let path = std::env::temp_dir().join(format!("alt-test-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_landmarks(path, std::slice::from_ref(&tables)).unwrap();
+        let read = read_landmarks(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].mode(), TravelMode::Bike);
+        assert_eq!(read[0].landmarks(), tables.landmarks());
+        for (src_id, dest_id) in sample_pairs(&graph, TravelMode::Bike, PAIRS) {
+            assert_eq!(read[0].lower_bound(src_id, dest_id), tables.lower_bound(src_id, dest_id));
+        }
+    }
+}
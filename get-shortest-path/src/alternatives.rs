@@ -0,0 +1,128 @@
+use crate::database::RawNode;
+use crate::dijkstra::{edge_weight, great_circle_heuristic, search, NodeCache, SEARCH_TIMEOUT};
+use crate::mode::TravelMode;
+use crate::source::GraphSource;
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tokio::time::Instant;
+
+use lambda_runtime::tracing::{info, error};
+
+// Every edge of a found route gets this much more expensive for the following searches
+const PENALTY_FACTOR: f64 = 1.4;
+
+// Routes sharing more than this fraction of their length with the best route are dropped
+const MAX_OVERLAP: f64 = 0.8;
+
+// Routes longer than this multiple of the best route are not worth offering
+const MAX_STRETCH: f64 = 1.5;
+
+// Penalized searches allowed per requested route before giving up
+const ATTEMPTS_PER_ROUTE: usize = 3;
+
+/// One of the routes offered to the user, best route first.
+#[derive(Clone, Debug, Serialize)]
+pub struct AlternativeRoute {
+    pub path: Vec<[f64; 2]>,
+    #[serde(skip)]
+    pub node_ids: Vec<i64>,
+    /// Real length in meters, without penalties.
+    pub distance: f64,
+    /// Fraction of this route's length shared with the best route.
+    pub overlap: f64,
+}
+
+fn undirected(from: i64, to: i64) -> (i64, i64) {
+    (from.min(to), from.max(to))
+}
+
+// Unpenalized length of every edge on the path
+fn edge_lengths<S: GraphSource>(mode: TravelMode, node_ids: &[i64], nodes: &NodeCache<S>) -> Vec<((i64, i64), f64)> {
+    node_ids
+        .windows(2)
+        .filter_map(|pair| {
+            let (from, to) = (nodes.get(pair[0])?, nodes.get(pair[1])?);
+            Some((undirected(from.id, to.id), edge_weight(mode, &from, &to)))
+        })
+        .collect()
+}
+
+// Penalty method: search repeatedly, making the edges of every route found more
+// expensive, and keep the results that are short enough and differ enough from the best.
+pub async fn alternative_routes<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    src_node: RawNode,
+    dest_node: RawNode,
+    k: usize,
+) -> Vec<AlternativeRoute> {
+    info!("Alternative routes search started for {:?}, k = {}", mode, k);
+
+    let mut penalties: HashMap<(i64, i64), f64> = HashMap::new();
+    let mut routes: Vec<AlternativeRoute> = Vec::new();
+    let mut best_edges: HashSet<(i64, i64)> = HashSet::new();
+    let mut best_distance = 0.0;
+
+    for attempt in 0..k * ATTEMPTS_PER_ROUTE {
+        if routes.len() >= k {
+            break;
+        }
+
+        let penalized = |from: &RawNode, to: &RawNode| {
+            edge_weight(mode, from, to) * penalties.get(&undirected(from.id, to.id)).copied().unwrap_or(1.0)
+        };
+        let result = search(
+            source,
+            mode,
+            src_node.clone(),
+            dest_node.clone(),
+            |node| great_circle_heuristic(node, &dest_node),
+            penalized,
+        )
+        .await;
+        if result.node_ids.is_empty() || result.timed_out {
+            break;
+        }
+
+        let mut nodes = NodeCache::new(source, &[]);
+        if nodes.fetch(result.node_ids.iter().copied(), Instant::now() + SEARCH_TIMEOUT).await.is_err() {
+            error!("Fetching the nodes of route {} timed out", routes.len());
+            break;
+        }
+        let edges = edge_lengths(mode, &result.node_ids, &nodes);
+        for (edge, _) in &edges {
+            *penalties.entry(*edge).or_insert(1.0) *= PENALTY_FACTOR;
+        }
+
+        let distance: f64 = edges.iter().map(|(_, length)| length).sum();
+        if routes.is_empty() {
+            best_edges = edges.iter().map(|(edge, _)| *edge).collect();
+            best_distance = distance;
+        }
+        let shared: f64 = edges
+            .iter()
+            .filter(|(edge, _)| best_edges.contains(edge))
+            .map(|(_, length)| length)
+            .sum();
+        let overlap = if distance > 0.0 { shared / distance } else { 1.0 };
+
+        let is_alternative = routes.is_empty()
+            || (overlap <= MAX_OVERLAP
+                && distance <= best_distance * MAX_STRETCH
+                && routes.iter().all(|route| route.node_ids != result.node_ids));
+        if !is_alternative {
+            continue;
+        }
+
+        info!("Route {} found on attempt {}: {:.1} m, overlap {:.2}", routes.len(), attempt, distance, overlap);
+        routes.push(AlternativeRoute {
+            path: result.path,
+            node_ids: result.node_ids,
+            distance,
+            overlap,
+        });
+    }
+
+    routes
+}
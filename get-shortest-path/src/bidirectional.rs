@@ -0,0 +1,263 @@
+use crate::database::RawNode;
+use crate::dijkstra::{edge_weight, path_coordinates, reconstruct_node_ids, NodeCache, SearchResult, State, SEARCH_TIMEOUT};
+use crate::mode::TravelMode;
+use crate::source::GraphSource;
+
+use std::collections::{BinaryHeap, HashMap};
+use tokio::time::Instant;
+
+use lambda_runtime::tracing::{info, error};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    Forward,
+    Backward,
+}
+
+// One half of the search. Backward "predecessors" point toward the destination.
+#[derive(Default)]
+struct Side {
+    distances: HashMap<i64, f64>,
+    predecessors: HashMap<i64, i64>,
+    heap: BinaryHeap<State<i64>>,
+}
+
+impl Side {
+    fn new(start: &RawNode) -> Side {
+        let mut side = Side::default();
+        side.distances.insert(start.id, 0.0);
+        side.heap.push(State {
+            cost: 0.0,
+            node: start.id,
+        });
+        side
+    }
+
+    fn distance(&self, id: i64) -> f64 {
+        *self.distances.get(&id).unwrap_or(&f64::MAX)
+    }
+
+    fn min_cost(&self) -> Option<f64> {
+        self.heap.peek().map(|state| state.cost)
+    }
+}
+
+// Bidirectional Dijkstra: a forward search from the source and a backward search over
+// reversed edges from the destination, always advancing the side with the smaller key.
+// Once the two smallest keys add up to at least the best meeting distance found so
+// far, no unexplored path can be shorter, so the result matches plain Dijkstra.
+pub async fn bidirectional_dijkstra<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    src_node: RawNode,
+    dest_node: RawNode,
+) -> SearchResult {
+    info!("Bidirectional Dijkstra execution started for {:?}", mode);
+
+    let start_time = Instant::now();
+    let deadline = start_time + SEARCH_TIMEOUT;
+
+    let mut forward = Side::new(&src_node);
+    let mut backward = Side::new(&dest_node);
+    let mut nodes = NodeCache::new(source, &[&src_node, &dest_node]);
+
+    // Best complete path found so far and the node where its two halves meet
+    let mut best_distance = if src_node.id == dest_node.id { 0.0 } else { f64::MAX };
+    let mut meeting_node = src_node.id;
+
+    let mut last_computed_node = src_node.id; // Last node settled by the forward search
+    let mut expanded_count = 0;
+    let mut timed_out = false;
+
+    while let (Some(forward_min), Some(backward_min)) = (forward.min_cost(), backward.min_cost()) {
+        if forward_min + backward_min >= best_distance {
+            break;
+        }
+
+        if start_time.elapsed() > SEARCH_TIMEOUT {
+            timed_out = true;
+            break;
+        }
+
+        let direction = if forward_min <= backward_min { Direction::Forward } else { Direction::Backward };
+        let (side, other) = match direction {
+            Direction::Forward => (&mut forward, &backward),
+            Direction::Backward => (&mut backward, &forward),
+        };
+
+        let State { cost, node: node_id } = side.heap.pop().unwrap();
+        if cost > side.distance(node_id) {
+            continue;
+        }
+        expanded_count += 1;
+        if direction == Direction::Forward {
+            last_computed_node = node_id;
+        }
+
+        let candidates = |node: &RawNode| match direction {
+            Direction::Forward => node.adjacency(mode).to_vec(),
+            Direction::Backward => node.reverse_adjacency(mode).to_vec(),
+        };
+        let Some(candidate_ids) = nodes.get(node_id).map(|node| candidates(&node)) else {
+            continue;
+        };
+        if nodes.fetch(candidate_ids.iter().copied(), deadline).await.is_err() {
+            timed_out = true;
+            break;
+        }
+
+        let node = nodes.get(node_id).unwrap();
+        for next_node_id in &candidate_ids {
+            let Some(next_node) = nodes.get(*next_node_id) else {
+                continue;
+            };
+
+            // Backward edges run next -> node, which only exist if next may travel to node
+            let weight = match direction {
+                Direction::Forward => edge_weight(mode, &node, &next_node),
+                Direction::Backward if next_node.adjacency(mode).contains(&node.id) => edge_weight(mode, &next_node, &node),
+                Direction::Backward => continue,
+            };
+            let next_cost = cost + weight;
+
+            if next_cost < side.distance(next_node.id) {
+                side.distances.insert(next_node.id, next_cost);
+                side.predecessors.insert(next_node.id, node.id);
+                side.heap.push(State {
+                    cost: next_cost,
+                    node: next_node.id,
+                });
+            }
+
+            let through = side.distance(next_node.id) + other.distance(next_node.id);
+            if other.distances.contains_key(&next_node.id) && through < best_distance {
+                best_distance = through;
+                meeting_node = next_node.id;
+            }
+        }
+    }
+
+    info!(
+        "Bidirectional search completed in {:?}. Nodes expanded: {}, total queries: {}",
+        start_time.elapsed(),
+        expanded_count,
+        nodes.query_count()
+    );
+    nodes.log_statistics();
+
+    if timed_out {
+        error!("Search timed out after {:?}. Queries executed: {}", SEARCH_TIMEOUT, nodes.query_count());
+    }
+
+    // On timeout return the forward path to the last computed node
+    let node_ids = if timed_out {
+        reconstruct_node_ids(&forward.predecessors, src_node.id, last_computed_node)
+    } else if best_distance < f64::MAX {
+        let mut node_ids = reconstruct_node_ids(&forward.predecessors, src_node.id, meeting_node);
+        let mut current = meeting_node;
+        while let Some(next) = backward.predecessors.get(&current) {
+            node_ids.push(*next);
+            current = *next;
+        }
+        node_ids
+    } else {
+        info!("No path found from node {} to node {}", src_node.id, dest_node.id);
+        Vec::new()
+    };
+
+    let distance = if node_ids.is_empty() {
+        0.0
+    } else if timed_out {
+        forward.distance(last_computed_node)
+    } else {
+        best_distance
+    };
+
+    SearchResult {
+        path: path_coordinates(source, mode, &node_ids, &nodes),
+        distance,
+        duration: distance / mode.speed_mps(),
+        node_ids,
+        nodes_expanded: expanded_count,
+        timed_out,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra;
+    use crate::graph::Graph;
+    use crate::test_support;
+
+    fn node(id: i64, lat: f64, lon: f64, foot: Vec<i64>, car: Vec<i64>, bike: Vec<i64>) -> RawNode {
+        RawNode {
+            car_adjacency_list: Some(car),
+            bike_adjacency_list: Some(bike),
+            ..test_support::node(id, lat, lon, foot)
+        }
+    }
+
+    // A block with a diagonal through its center 5, cars circling it one way and bikes
+    // the other way, plus the separate street 6 - 7 nothing else connects to
+    fn block() -> Graph {
+        Graph::from_nodes(vec![
+            node(1, 40.0, -74.0, vec![2, 4, 5], vec![2, 5], vec![4]),
+            node(2, 40.0, -73.999, vec![1, 3], vec![3], vec![1]),
+            node(3, 40.001, -73.999, vec![2, 4, 5], vec![4], vec![2, 5]),
+            node(4, 40.001, -74.0, vec![1, 3], vec![1], vec![3]),
+            node(5, 40.0005, -73.9995, vec![1, 3], vec![3], vec![1]),
+            node(6, 40.002, -74.0, vec![7], vec![7], vec![7]),
+            node(7, 40.002, -73.999, vec![6], vec![], vec![6]),
+        ])
+    }
+
+    // Every pair in every mode, including the one-way detours and unreachable pairs
+    #[tokio::test]
+    async fn matches_dijkstra() {
+        let graph = block();
+        for mode in [TravelMode::Foot, TravelMode::Car, TravelMode::Bike] {
+            let mut unreachable = 0;
+            for src_id in 1..=7 {
+                for dest_id in 1..=7 {
+                    let src_node = graph.get_node(src_id).unwrap().clone();
+                    let dest_node = graph.get_node(dest_id).unwrap().clone();
+                    let expected = dijkstra::search(
+                        &graph,
+                        mode,
+                        src_node.clone(),
+                        dest_node.clone(),
+                        |_| 0.0,
+                        |from, to| edge_weight(mode, from, to),
+                    )
+                    .await;
+                    let result = bidirectional_dijkstra(&graph, mode, src_node, dest_node).await;
+
+                    let context = format!("{:?} {} -> {}", mode, src_id, dest_id);
+                    assert_eq!(result.node_ids.is_empty(), expected.node_ids.is_empty(), "{}", context);
+                    assert!((result.distance - expected.distance).abs() < 1e-6, "{}", context);
+                    if result.node_ids.is_empty() {
+                        unreachable += 1;
+                        continue;
+                    }
+                    assert_eq!(result.node_ids.first(), Some(&src_id), "{}", context);
+                    assert_eq!(result.node_ids.last(), Some(&dest_id), "{}", context);
+                    // Every step of the path is an edge the mode may travel
+                    for step in result.node_ids.windows(2) {
+                        assert!(graph.get_node(step[0]).unwrap().adjacency(mode).contains(&step[1]), "{}", context);
+                    }
+                }
+            }
+            // The street 6 - 7 is cut off from the block, and cars can't leave 7
+            let expected_unreachable = if mode == TravelMode::Car { 2 * 5 * 2 + 1 } else { 2 * 5 * 2 };
+            assert_eq!(unreachable, expected_unreachable, "{:?}", mode);
+        }
+
+        // Cars go around the block against the diagonal, bikes take it
+        let (from, to) = (graph.get_node(3).unwrap(), graph.get_node(1).unwrap());
+        let car = bidirectional_dijkstra(&graph, TravelMode::Car, from.clone(), to.clone()).await;
+        assert_eq!(car.node_ids, [3, 4, 1]);
+        let bike = bidirectional_dijkstra(&graph, TravelMode::Bike, from.clone(), to.clone()).await;
+        assert_eq!(bike.node_ids, [3, 5, 1]);
+    }
+}
@@ -0,0 +1,86 @@
+// Offline preprocessing for ALT: pick landmarks for every travel mode, compute their
+// distance tables and write them to disk for the Lambda to load through ALT_PATH.
+//
+//   GRAPH_BACKEND=csv cargo run --release --bin build_alt -- alt.bin [--landmarks 8]
+
+use dotenv::dotenv;
+use std::env;
+use std::io;
+use std::time::Instant;
+
+use get_shortest_path::alt::{self, LandmarkTables};
+use get_shortest_path::mode::TravelMode;
+use get_shortest_path::source::{self, Backend};
+
+const DEFAULT_LANDMARKS: usize = 8;
+
+const USAGE: &str = "Usage: build_alt [OUTPUT] [--landmarks COUNT]
+
+Writes landmark tables for every travel mode to OUTPUT (default $ALT_PATH or alt.bin).";
+
+// Print the usage and fail, so a mistyped flag never overwrites the tables
+fn usage_error(message: &str) -> io::Error {
+    eprintln!("{}\n\n{}", message, USAGE);
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    dotenv().ok();
+
+    let mut output_path = None;
+    let mut landmark_count = DEFAULT_LANDMARKS;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            "--landmarks" => {
+                landmark_count = args
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .ok_or_else(|| usage_error("--landmarks expects a number"))?;
+            }
+            _ if arg.starts_with('-') => return Err(usage_error(&format!("Unknown option {}", arg))),
+            _ if output_path.is_some() => return Err(usage_error(&format!("Unexpected argument {}", arg))),
+            _ => output_path = Some(arg),
+        }
+    }
+    let output_path = output_path
+        .or_else(|| env::var("ALT_PATH").ok())
+        .unwrap_or_else(|| "alt.bin".to_string());
+
+    let graph = match source::load_backend().await? {
+        Backend::Memory(graph) | Backend::Csv(graph) => graph,
+        Backend::Postgres(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "build_alt needs the whole graph, use GRAPH_BACKEND=memory or csv",
+            ));
+        }
+    };
+    println!("Graph has {} nodes and {} adjacency entries", graph.node_count(), graph.edge_count());
+
+    let mut all_tables = Vec::new();
+    for mode in [TravelMode::Foot, TravelMode::Car, TravelMode::Bike] {
+        let build_start = Instant::now();
+        let tables = LandmarkTables::build(&graph, mode, landmark_count);
+        println!(
+            "Preprocessed {:?}: {} landmarks, {} table entries ({:.1} KiB) in {:?}",
+            mode,
+            tables.landmarks().len(),
+            tables.entry_count(),
+            tables.table_bytes() as f64 / 1024.0,
+            build_start.elapsed()
+        );
+        all_tables.push(tables);
+    }
+
+    alt::write_landmarks(&output_path, &all_tables)?;
+    let file_size = std::fs::metadata(&output_path)?.len();
+    println!("Wrote landmark tables to {} ({} bytes)", output_path, file_size);
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+// Offline preprocessing: contract the routing graph for every travel mode and write the
+// hierarchies to disk for the Lambda to load through CH_PATH.
+//
+//   GRAPH_BACKEND=csv cargo run --release --bin build_ch -- ch.bin
+
+use dotenv::dotenv;
+use std::env;
+use std::io;
+use std::time::Instant;
+
+use get_shortest_path::ch::{self, ContractionHierarchy};
+use get_shortest_path::mode::TravelMode;
+use get_shortest_path::source::{self, Backend};
+
+const USAGE: &str = "Usage: build_ch [OUTPUT]
+
+Writes contraction hierarchies for every travel mode to OUTPUT (default $CH_PATH or ch.bin).";
+
+// Print the usage and fail, so a mistyped flag never overwrites the hierarchies
+fn usage_error(message: &str) -> io::Error {
+    eprintln!("{}\n\n{}", message, USAGE);
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    dotenv().ok();
+
+    let mut output_path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ if arg.starts_with('-') => return Err(usage_error(&format!("Unknown option {}", arg))),
+            _ if output_path.is_some() => return Err(usage_error(&format!("Unexpected argument {}", arg))),
+            _ => output_path = Some(arg),
+        }
+    }
+    let output_path = output_path
+        .or_else(|| env::var("CH_PATH").ok())
+        .unwrap_or_else(|| "ch.bin".to_string());
+
+    let graph = match source::load_backend().await? {
+        Backend::Memory(graph) | Backend::Csv(graph) => graph,
+        Backend::Postgres(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "build_ch needs the whole graph, use GRAPH_BACKEND=memory or csv",
+            ));
+        }
+    };
+    println!("Graph has {} nodes and {} adjacency entries", graph.node_count(), graph.edge_count());
+
+    let mut hierarchies = Vec::new();
+    for mode in [TravelMode::Foot, TravelMode::Car, TravelMode::Bike] {
+        let build_start = Instant::now();
+        let hierarchy = ContractionHierarchy::build(&graph, mode);
+        println!(
+            "Contracted {:?}: {} nodes, {} arcs in {:?}",
+            mode,
+            hierarchy.node_count(),
+            hierarchy.arc_count(),
+            build_start.elapsed()
+        );
+        hierarchies.push(hierarchy);
+    }
+
+    ch::write_hierarchies(&output_path, &hierarchies)?;
+    println!("Wrote contraction hierarchies to {}", output_path);
+
+    Ok(())
+}
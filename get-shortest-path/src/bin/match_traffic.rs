@@ -0,0 +1,138 @@
+// Offline matching of traffic counts onto graph edges: every counted segment gets the
+// directed car edges it describes, and their hourly volumes become the edge_traffic
+// rows departure-time routing reads.
+//
+//   GRAPH_BACKEND=memory cargo run --release --bin match_traffic
+//   GRAPH_BACKEND=csv WAYS_TXT=output_ways.txt cargo run --release --bin match_traffic -- \
+//       --counts traffic_counts.csv --output edge_traffic.csv
+//
+// Counts come from the traffic_counts table written by import-traffic, or from its
+// --csv export. Without --output the matches are written to the edge_traffic and
+// traffic_segment_matches tables.
+
+use dotenv::dotenv;
+use std::env;
+use std::io;
+use std::time::Instant;
+
+use get_shortest_path::database;
+use get_shortest_path::matching::{self, CountedSegment};
+use get_shortest_path::source::{self, Backend};
+use get_shortest_path::traffic::TrafficCount;
+
+fn arg_value(args: &[String], flag: &str) -> io::Result<Option<String>> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(position) => args
+            .get(position + 1)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} expects a path", flag))),
+        None => Ok(None),
+    }
+}
+
+fn read_counts_csv(path: &str) -> io::Result<Vec<TrafficCount>> {
+    let mut reader = csv::Reader::from_path(path).map_err(io::Error::other)?;
+    reader
+        .deserialize()
+        .collect::<Result<Vec<TrafficCount>, _>>()
+        .map_err(io::Error::other)
+}
+
+fn print_report(segments: &[CountedSegment], report: &matching::MatchReport) {
+    for segment_match in &report.matches {
+        println!(
+            "  {:>6} {} {:<28} -> way {} '{}', {} edges, {:.1} m, name {:.2}, {:?} ({:.2})",
+            segment_match.segment_id,
+            segment_match.direction,
+            segment_match.street,
+            segment_match.way_id,
+            segment_match.way_name.as_deref().unwrap_or(""),
+            segment_match.edges.len(),
+            segment_match.distance,
+            segment_match.name_similarity,
+            segment_match.confidence,
+            segment_match.score
+        );
+    }
+    for unmatched in &report.unmatched {
+        println!(
+            "  {:>6} {} {:<28} unmatched: {}",
+            unmatched.segment_id, unmatched.direction, unmatched.street, unmatched.reason
+        );
+    }
+    println!(
+        "Matched {} of {} counted segments, {} unmatched",
+        report.matches.len(),
+        segments.len(),
+        report.unmatched.len()
+    );
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    dotenv().ok();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let counts_path = arg_value(&args, "--counts")?;
+    let output_path = arg_value(&args, "--output")?;
+
+    let graph = match source::load_source().await? {
+        Backend::Memory(graph) | Backend::Csv(graph) => graph,
+        Backend::Postgres(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "match_traffic needs the whole graph, use GRAPH_BACKEND=memory or csv",
+            ));
+        }
+    };
+    if graph.way_count() == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No way tags loaded, street names are needed to match counts (planet_osm_ways or WAYS_TXT)",
+        ));
+    }
+    println!("Graph has {} nodes and tags of {} ways", graph.node_count(), graph.way_count());
+
+    let pool = match (&counts_path, &output_path) {
+        (Some(_), Some(_)) => None,
+        _ => {
+            let database_url = env::var("DATABASE_URL")
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DATABASE_URL must be set"))?;
+            Some(database::create_pool(&database_url).await?)
+        }
+    };
+
+    let counts = match (&counts_path, &pool) {
+        (Some(path), _) => read_counts_csv(path)?,
+        (None, Some(pool)) => database::get_traffic_counts(pool).await?,
+        (None, None) => unreachable!("a pool is created whenever --counts is missing"),
+    };
+    let segments = matching::group_counts(&counts);
+    println!("Read {} hourly counts over {} segments and directions", counts.len(), segments.len());
+
+    let match_start = Instant::now();
+    let report = matching::match_segments(&graph, &segments);
+    println!("Matched in {:?}", match_start.elapsed());
+    print_report(&segments, &report);
+
+    let volumes = matching::edge_volumes(&segments, &report.matches);
+    match (&output_path, &pool) {
+        (Some(path), _) => {
+            let mut writer = csv::Writer::from_path(path).map_err(io::Error::other)?;
+            for volume in &volumes {
+                writer.serialize(volume).map_err(io::Error::other)?;
+            }
+            writer.flush()?;
+            println!("Wrote {} edge volumes to {}", volumes.len(), path);
+        }
+        (None, Some(pool)) => {
+            database::write_edge_traffic(pool, &volumes).await?;
+            database::write_segment_matches(pool, &report.matches).await?;
+            println!("Wrote {} edge volumes to edge_traffic and the matches to traffic_segment_matches", volumes.len());
+        }
+        (None, None) => unreachable!("a pool is created whenever --output is missing"),
+    }
+
+    Ok(())
+}
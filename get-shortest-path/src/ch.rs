@@ -0,0 +1,418 @@
+use crate::dijkstra::edge_weight;
+use crate::graph::Graph;
+use crate::mode::TravelMode;
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+
+// Witness searches give up after settling this many nodes and add the shortcut anyway.
+// A superfluous shortcut costs a little space, never correctness.
+const WITNESS_SETTLE_LIMIT: usize = 500;
+
+// Arc of the hierarchy. Shortcuts remember the contracted node they bypass so a
+// path can be unpacked back into original edges.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct Arc {
+    target: u32,
+    weight: f64,
+    middle: Option<u32>,
+}
+
+// Min-heap entry over node indices
+#[derive(Debug)]
+pub(crate) struct Entry {
+    pub(crate) cost: f64,
+    pub(crate) node: u32,
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Entry {}
+
+/// Outcome of a hierarchy query, in OSM node ids.
+#[derive(Clone, Debug)]
+pub struct ChPath {
+    pub node_ids: Vec<i64>,
+    pub distance: f64,
+    pub nodes_expanded: usize,
+}
+
+/// Contraction hierarchy for one travel mode.
+///
+/// Built offline by `build_ch` from the in-memory graph; a query only relaxes arcs
+/// towards more important nodes, from both ends, so it settles a tiny fraction of
+/// what Dijkstra does while returning the same shortest distance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractionHierarchy {
+    mode: TravelMode,
+    node_ids: Vec<i64>,
+    rank: Vec<u32>,
+    // Arcs u -> v with rank[v] > rank[u], stored at u
+    upward: Vec<Vec<Arc>>,
+    // Arcs u -> v with rank[u] > rank[v], stored reversed at v (target = u)
+    downward: Vec<Vec<Arc>>,
+    #[serde(skip)]
+    index: HashMap<i64, u32>,
+}
+
+// Adjacency used while contracting, keyed by neighbor so parallel arcs keep the shorter one
+struct Builder {
+    out_arcs: Vec<HashMap<u32, (f64, Option<u32>)>>,
+    in_arcs: Vec<HashMap<u32, (f64, Option<u32>)>>,
+    contracted: Vec<bool>,
+    contracted_neighbors: Vec<usize>,
+}
+
+impl Builder {
+    fn add_arc(&mut self, from: u32, to: u32, weight: f64, middle: Option<u32>) {
+        let current = self.out_arcs[from as usize].get(&to).map(|(w, _)| *w).unwrap_or(f64::MAX);
+        if weight < current {
+            self.out_arcs[from as usize].insert(to, (weight, middle));
+            self.in_arcs[to as usize].insert(from, (weight, middle));
+        }
+    }
+
+    // Bounded Dijkstra from `start` over uncontracted nodes, skipping `excluded`
+    fn witness_distances(&self, start: u32, excluded: u32, max_distance: f64) -> HashMap<u32, f64> {
+        let mut distances: HashMap<u32, f64> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        let mut settled = 0;
+
+        distances.insert(start, 0.0);
+        heap.push(Entry { cost: 0.0, node: start });
+
+        while let Some(Entry { cost, node }) = heap.pop() {
+            if cost > distances[&node] {
+                continue;
+            }
+            settled += 1;
+            if cost > max_distance || settled > WITNESS_SETTLE_LIMIT {
+                break;
+            }
+
+            for (&next, &(weight, _)) in &self.out_arcs[node as usize] {
+                if next == excluded || self.contracted[next as usize] {
+                    continue;
+                }
+                let next_cost = cost + weight;
+                if next_cost < *distances.get(&next).unwrap_or(&f64::MAX) {
+                    distances.insert(next, next_cost);
+                    heap.push(Entry { cost: next_cost, node: next });
+                }
+            }
+        }
+
+        distances
+    }
+
+    // Shortcuts needed to contract `node` without changing any shortest distance
+    fn shortcuts(&self, node: u32) -> Vec<(u32, u32, f64)> {
+        let mut shortcuts = Vec::new();
+        let outgoing: Vec<(u32, f64)> = self.out_arcs[node as usize]
+            .iter()
+            .filter(|(next, _)| !self.contracted[**next as usize])
+            .map(|(next, (weight, _))| (*next, *weight))
+            .collect();
+        if outgoing.is_empty() {
+            return shortcuts;
+        }
+        let max_outgoing = outgoing.iter().map(|(_, weight)| *weight).fold(0.0, f64::max);
+
+        for (&from, &(in_weight, _)) in &self.in_arcs[node as usize] {
+            if self.contracted[from as usize] {
+                continue;
+            }
+            let witnesses = self.witness_distances(from, node, in_weight + max_outgoing);
+            for &(to, out_weight) in &outgoing {
+                if to == from {
+                    continue;
+                }
+                let via = in_weight + out_weight;
+                if *witnesses.get(&to).unwrap_or(&f64::MAX) > via {
+                    shortcuts.push((from, to, via));
+                }
+            }
+        }
+
+        shortcuts
+    }
+
+    fn degree(&self, node: u32) -> usize {
+        let uncontracted = |arcs: &HashMap<u32, (f64, Option<u32>)>| {
+            arcs.keys().filter(|next| !self.contracted[**next as usize]).count()
+        };
+        uncontracted(&self.out_arcs[node as usize]) + uncontracted(&self.in_arcs[node as usize])
+    }
+
+    // Edge difference plus contracted neighbors, lower contracts first
+    fn priority(&self, node: u32) -> f64 {
+        let shortcuts = self.shortcuts(node).len() as f64;
+        shortcuts - self.degree(node) as f64 + self.contracted_neighbors[node as usize] as f64
+    }
+}
+
+impl ContractionHierarchy {
+    /// Contract every node of `graph` for `mode`.
+    pub fn build(graph: &Graph, mode: TravelMode) -> ContractionHierarchy {
+        let mut node_ids: Vec<i64> = graph.nodes().map(|node| node.id).collect();
+        node_ids.sort_unstable();
+        let index: HashMap<i64, u32> = node_ids.iter().enumerate().map(|(i, id)| (*id, i as u32)).collect();
+        let node_count = node_ids.len();
+
+        let mut builder = Builder {
+            out_arcs: vec![HashMap::new(); node_count],
+            in_arcs: vec![HashMap::new(); node_count],
+            contracted: vec![false; node_count],
+            contracted_neighbors: vec![0; node_count],
+        };
+        for node in graph.nodes() {
+            for next_id in node.adjacency(mode) {
+                let (Some(&from), Some(&to), Some(next_node)) = (index.get(&node.id), index.get(next_id), graph.get_node(*next_id)) else {
+                    continue;
+                };
+                if from != to {
+                    builder.add_arc(from, to, edge_weight(mode, node, next_node), None);
+                }
+            }
+        }
+
+        // Lazy updates: a popped node is re-queued if its priority got worse in the meantime
+        let mut queue = BinaryHeap::new();
+        for node in 0..node_count as u32 {
+            queue.push(Entry { cost: builder.priority(node), node });
+        }
+
+        let mut rank = vec![0u32; node_count];
+        let mut next_rank = 0;
+        while let Some(Entry { node, .. }) = queue.pop() {
+            if builder.contracted[node as usize] {
+                continue;
+            }
+            let priority = builder.priority(node);
+            if let Some(top) = queue.peek() {
+                if priority > top.cost {
+                    queue.push(Entry { cost: priority, node });
+                    continue;
+                }
+            }
+
+            for (from, to, weight) in builder.shortcuts(node) {
+                builder.add_arc(from, to, weight, Some(node));
+            }
+            builder.contracted[node as usize] = true;
+            rank[node as usize] = next_rank;
+            next_rank += 1;
+
+            let neighbors: Vec<u32> = builder.out_arcs[node as usize]
+                .keys()
+                .chain(builder.in_arcs[node as usize].keys())
+                .copied()
+                .collect();
+            for neighbor in neighbors {
+                builder.contracted_neighbors[neighbor as usize] += 1;
+            }
+        }
+
+        let mut upward = vec![Vec::new(); node_count];
+        let mut downward = vec![Vec::new(); node_count];
+        for (from, arcs) in builder.out_arcs.iter().enumerate() {
+            for (&to, &(weight, middle)) in arcs {
+                if rank[from] < rank[to as usize] {
+                    upward[from].push(Arc { target: to, weight, middle });
+                } else {
+                    downward[to as usize].push(Arc { target: from as u32, weight, middle });
+                }
+            }
+        }
+
+        ContractionHierarchy {
+            mode,
+            node_ids,
+            rank,
+            upward,
+            downward,
+            index,
+        }
+    }
+
+    pub fn mode(&self) -> TravelMode {
+        self.mode
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.node_ids.len()
+    }
+
+    pub fn arc_count(&self) -> usize {
+        self.upward.iter().chain(self.downward.iter()).map(|arcs| arcs.len()).sum()
+    }
+
+    /// Shortest path between two OSM node ids, None when either is unknown or unreachable.
+    pub fn shortest_path(&self, src_id: i64, dest_id: i64) -> Option<ChPath> {
+        let source = *self.index.get(&src_id)?;
+        let target = *self.index.get(&dest_id)?;
+
+        let mut forward: HashMap<u32, (f64, Option<u32>)> = HashMap::new();
+        let mut backward: HashMap<u32, (f64, Option<u32>)> = HashMap::new();
+        let mut forward_heap = BinaryHeap::new();
+        let mut backward_heap = BinaryHeap::new();
+        forward.insert(source, (0.0, None));
+        backward.insert(target, (0.0, None));
+        forward_heap.push(Entry { cost: 0.0, node: source });
+        backward_heap.push(Entry { cost: 0.0, node: target });
+
+        let mut best = if source == target { Some((0.0, source)) } else { None };
+        let mut nodes_expanded = 0;
+
+        // Each side only climbs the hierarchy; a side stops once its smallest key
+        // can no longer beat the best meeting point
+        loop {
+            let best_distance = best.map(|(distance, _)| distance).unwrap_or(f64::MAX);
+            let forward_min = forward_heap.peek().map(|entry| entry.cost).filter(|cost| *cost < best_distance);
+            let backward_min = backward_heap.peek().map(|entry| entry.cost).filter(|cost| *cost < best_distance);
+
+            let (heap, labels, other, arcs) = match (forward_min, backward_min) {
+                (None, None) => break,
+                (Some(f), Some(b)) if b < f => (&mut backward_heap, &mut backward, &forward, &self.downward),
+                (None, Some(_)) => (&mut backward_heap, &mut backward, &forward, &self.downward),
+                _ => (&mut forward_heap, &mut forward, &backward, &self.upward),
+            };
+
+            let Entry { cost, node } = heap.pop().unwrap();
+            if cost > labels[&node].0 {
+                continue;
+            }
+            nodes_expanded += 1;
+
+            if let Some((other_cost, _)) = other.get(&node) {
+                if cost + other_cost < best_distance {
+                    best = Some((cost + other_cost, node));
+                }
+            }
+
+            for arc in &arcs[node as usize] {
+                let next_cost = cost + arc.weight;
+                if next_cost < labels.get(&arc.target).map(|(c, _)| *c).unwrap_or(f64::MAX) {
+                    labels.insert(arc.target, (next_cost, Some(node)));
+                    heap.push(Entry { cost: next_cost, node: arc.target });
+                }
+            }
+        }
+
+        let (distance, meeting) = best?;
+
+        // source .. meeting along the forward labels, meeting .. target along the backward ones
+        let mut up = vec![meeting];
+        while let Some((_, Some(previous))) = forward.get(up.last().unwrap()) {
+            up.push(*previous);
+        }
+        up.reverse();
+        let mut current = meeting;
+        while let Some((_, Some(next))) = backward.get(&current) {
+            up.push(*next);
+            current = *next;
+        }
+
+        let mut path = vec![up[0]];
+        for pair in up.windows(2) {
+            self.unpack(pair[0], pair[1], &mut path);
+        }
+
+        Some(ChPath {
+            node_ids: path.into_iter().map(|node| self.node_ids[node as usize]).collect(),
+            distance,
+            nodes_expanded,
+        })
+    }
+
+    fn arc(&self, from: u32, to: u32) -> Option<&Arc> {
+        if self.rank[from as usize] < self.rank[to as usize] {
+            self.upward[from as usize].iter().find(|arc| arc.target == to)
+        } else {
+            self.downward[to as usize].iter().find(|arc| arc.target == from)
+        }
+    }
+
+    // Replace the arc from -> to by the original edges it stands for, appending everything after `from`
+    fn unpack(&self, from: u32, to: u32, path: &mut Vec<u32>) {
+        match self.arc(from, to).and_then(|arc| arc.middle) {
+            Some(middle) => {
+                self.unpack(from, middle, path);
+                self.unpack(middle, to, path);
+            }
+            None => path.push(to),
+        }
+    }
+}
+
+/// Write one hierarchy per mode to `path`.
+pub fn write_hierarchies(path: &str, hierarchies: &[ContractionHierarchy]) -> Result<(), io::Error> {
+    let writer = BufWriter::new(File::create(path)?);
+    bincode::serialize_into(writer, hierarchies).map_err(io::Error::other)
+}
+
+/// Read the hierarchies written by `write_hierarchies`.
+pub fn read_hierarchies(path: &str) -> Result<Vec<ContractionHierarchy>, io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut hierarchies: Vec<ContractionHierarchy> = bincode::deserialize_from(reader).map_err(io::Error::other)?;
+    for hierarchy in &mut hierarchies {
+        hierarchy.index = hierarchy.node_ids.iter().enumerate().map(|(i, id)| (*id, i as u32)).collect();
+    }
+    Ok(hierarchies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra;
+    use crate::test_support::{princeton, sample_pairs};
+
+    // Number of node pairs compared against plain Dijkstra per mode
+    const PAIRS: usize = 200;
+
+    // Hierarchy distances must equal plain Dijkstra on a spread of node pairs
+    #[tokio::test]
+    async fn matches_dijkstra() {
+        let graph = princeton();
+        for mode in [TravelMode::Foot, TravelMode::Car, TravelMode::Bike] {
+            let hierarchy = ContractionHierarchy::build(&graph, mode);
+            for (src_id, dest_id) in sample_pairs(&graph, mode, PAIRS) {
+                let src_node = graph.get_node(src_id).unwrap().clone();
+                let dest_node = graph.get_node(dest_id).unwrap().clone();
+
+                let expected = dijkstra::dijkstra(&graph, mode, src_node, dest_node).await;
+                match hierarchy.shortest_path(src_id, dest_id) {
+                    Some(path) => assert!(
+                        !expected.node_ids.is_empty() && (path.distance - expected.distance).abs() < 1e-6,
+                        "{:?} {} -> {}: dijkstra {:.3} m, ch {:.3} m",
+                        mode,
+                        src_id,
+                        dest_id,
+                        expected.distance,
+                        path.distance
+                    ),
+                    None => assert!(expected.node_ids.is_empty(), "{:?} {} -> {}: ch found no path", mode, src_id, dest_id),
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,14 @@
-use sqlx::{postgres::PgPoolOptions};
+use sqlx::{postgres::PgPoolOptions, Postgres, QueryBuilder};
 use std::io;
 use serde::Deserialize;
 use sqlx::FromRow;
-use geoutils::{Location, Distance};
+use geoutils::Location;
+
+use crate::geocode::Place;
+use crate::mode::TravelMode;
+use crate::matching::SegmentMatch;
+use crate::traffic::{EdgeVolume, TrafficCount};
+use crate::ways::OsmWay;
 
 
 #[derive(Clone, Debug, Deserialize, FromRow)]
@@ -10,7 +16,66 @@ pub struct RawNode {
     pub id: i64,
     pub lon: f64,
     pub lat: f64,
-    pub adjacency_list: Vec<i64>
+    pub adjacency_list: Vec<i64>,
+    // Stored edge lengths in meters, parallel to adjacency_list; None when adjacent_nodes
+    // has no lengths column
+    #[sqlx(default)]
+    pub adjacency_lengths: Option<Vec<f64>>,
+    // Directed out-neighbors honoring oneway tags; None when the table predates them,
+    // the queries select NULL for columns adjacent_nodes does not have
+    #[sqlx(default)]
+    pub car_adjacency_list: Option<Vec<i64>>,
+    #[sqlx(default)]
+    pub bike_adjacency_list: Option<Vec<i64>>,
+    // Stored lengths parallel to the directed lists, only the CSV export has them
+    #[sqlx(default)]
+    pub car_adjacency_lengths: Option<Vec<f64>>,
+    #[sqlx(default)]
+    pub bike_adjacency_lengths: Option<Vec<f64>>,
+    // Directed in-neighbors, only filled in by the in-memory graph
+    #[sqlx(default)]
+    pub car_reverse_adjacency_list: Option<Vec<i64>>,
+    #[sqlx(default)]
+    pub bike_reverse_adjacency_list: Option<Vec<i64>>,
+}
+
+impl RawNode {
+    // Neighbors reachable from this node when travelling by `mode`
+    pub fn adjacency(&self, mode: TravelMode) -> &[i64] {
+        let directed = match mode {
+            TravelMode::Foot => None,
+            TravelMode::Car => self.car_adjacency_list.as_deref(),
+            TravelMode::Bike => self.bike_adjacency_list.as_deref(),
+        };
+        directed.unwrap_or(&self.adjacency_list)
+    }
+
+    // Candidate nodes that may reach this node when travelling by `mode`. Without
+    // reverse lists this is every walkable neighbor, callers check the edge direction.
+    pub fn reverse_adjacency(&self, mode: TravelMode) -> &[i64] {
+        let directed = match mode {
+            TravelMode::Foot => None,
+            TravelMode::Car => self.car_reverse_adjacency_list.as_deref(),
+            TravelMode::Bike => self.bike_reverse_adjacency_list.as_deref(),
+        };
+        directed.unwrap_or(&self.adjacency_list)
+    }
+
+    // Stored length of the edge `mode` travels to `neighbor_id`, if the table carries
+    // lengths. Without per-mode lengths the walking edge between the two nodes is used.
+    pub fn edge_length(&self, mode: TravelMode, neighbor_id: i64) -> Option<f64> {
+        let directed = match mode {
+            TravelMode::Foot => None,
+            TravelMode::Car => self.car_adjacency_list.as_deref().zip(self.car_adjacency_lengths.as_deref()),
+            TravelMode::Bike => self.bike_adjacency_list.as_deref().zip(self.bike_adjacency_lengths.as_deref()),
+        };
+        let (neighbors, lengths) = match directed {
+            Some(directed) => directed,
+            None => (self.adjacency_list.as_slice(), self.adjacency_lengths.as_deref()?),
+        };
+        let index = neighbors.iter().position(|id| *id == neighbor_id)?;
+        lengths.get(index).copied()
+    }
 }
 
 pub async fn create_pool(database_url: &str) -> Result<sqlx::PgPool, io::Error> {
@@ -23,25 +88,87 @@ pub async fn create_pool(database_url: &str) -> Result<sqlx::PgPool, io::Error>
 
 
 
+/// Optional columns of adjacent_nodes. Tables built by setup_psql.py only have
+/// `id, nodes`, import-osm adds the lengths and the directed lists.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AdjacencyColumns {
+    pub lengths: bool,
+    pub car_nodes: bool,
+    pub bike_nodes: bool,
+}
+
+impl AdjacencyColumns {
+    // Look the columns up once, every node query then selects only what exists
+    pub async fn detect(pool: &sqlx::PgPool) -> Result<AdjacencyColumns, io::Error> {
+        let query = r#"
+            SELECT column_name::TEXT
+            FROM information_schema.columns
+            WHERE table_schema = current_schema() AND table_name = 'adjacent_nodes';
+        "#;
+        let names: Vec<String> = sqlx::query_scalar(query)
+            .fetch_all(pool)
+            .await
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        let has = |column: &str| names.iter().any(|name| name == column);
+        Ok(AdjacencyColumns {
+            lengths: has("lengths"),
+            car_nodes: has("car_nodes"),
+            bike_nodes: has("bike_nodes"),
+        })
+    }
+
+    // Adjacency part of the SELECT list, NULL standing in for missing columns
+    fn select_list(&self) -> String {
+        let column = |present: bool, name: &str, sql_type: &str, alias: &str| {
+            if present {
+                format!("adjacent_nodes.{} AS {}", name, alias)
+            } else {
+                format!("NULL::{} AS {}", sql_type, alias)
+            }
+        };
+        [
+            "adjacent_nodes.nodes AS adjacency_list".to_string(),
+            column(self.lengths, "lengths", "FLOAT8[]", "adjacency_lengths"),
+            column(self.car_nodes, "car_nodes", "BIGINT[]", "car_adjacency_list"),
+            column(self.bike_nodes, "bike_nodes", "BIGINT[]", "bike_adjacency_list"),
+        ]
+        .join(",\n            ")
+    }
+}
+
+// Rows per INSERT statement, keeps each statement well under the Postgres bind limit
+const INSERT_CHUNK_SIZE: usize = 5000;
+
+async fn execute(pool: &sqlx::PgPool, query: &str) -> Result<(), io::Error> {
+    sqlx::query(query)
+        .execute(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    Ok(())
+}
+
 // Function to fetch node details from planet_osm_point table
-pub async fn get_node_by_id(pool: &sqlx::PgPool, osm_id: i64) -> Result<RawNode, io::Error> {
+pub async fn get_node_by_id(pool: &sqlx::PgPool, columns: AdjacencyColumns, osm_id: i64) -> Result<RawNode, io::Error> {
     // SQL query to select osm_id, longitude, latitude, and name from planet_osm_point
-    let query = r#"
+    let query = format!(
+        r#"
         SELECT
             planet_osm_nodes.id,
             (planet_osm_nodes.lon / 1e7)::FLOAT8 AS lon,
             (planet_osm_nodes.lat / 1e7)::FLOAT8 AS lat,
-            adjacent_nodes.nodes AS adjacency_list
+            {}
         FROM
             planet_osm_nodes
         JOIN
             adjacent_nodes ON planet_osm_nodes.id = adjacent_nodes.id
         WHERE
             planet_osm_nodes.id = $1;
-    "#;
+    "#,
+        columns.select_list()
+    );
 
     // Execute the query and bind the OSM ID
-    let node = sqlx::query_as::<_, RawNode>(query)
+    let node = sqlx::query_as::<_, RawNode>(&query)
         .bind(osm_id)
         .fetch_one(pool)
         .await
@@ -51,6 +178,278 @@ pub async fn get_node_by_id(pool: &sqlx::PgPool, osm_id: i64) -> Result<RawNode,
     Ok(node)
 }
 
+// Function to fetch a batch of nodes in one round-trip
+pub async fn get_nodes_by_ids(
+    pool: &sqlx::PgPool,
+    columns: AdjacencyColumns,
+    node_ids: &[i64],
+) -> Result<Vec<RawNode>, io::Error> {
+    let query = format!(
+        r#"
+        SELECT
+            planet_osm_nodes.id,
+            (planet_osm_nodes.lon / 1e7)::FLOAT8 AS lon,
+            (planet_osm_nodes.lat / 1e7)::FLOAT8 AS lat,
+            {}
+        FROM
+            planet_osm_nodes
+        JOIN
+            adjacent_nodes ON planet_osm_nodes.id = adjacent_nodes.id
+        WHERE planet_osm_nodes.id = ANY($1);
+    "#,
+        columns.select_list()
+    );
+
+    let nodes = sqlx::query_as::<_, RawNode>(&query)
+        .bind(node_ids) // Bind the array of node IDs
+        .fetch_all(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    Ok(nodes)
+}
+
+// Routable nodes inside a lat / lon box, the candidates for snapping a coordinate
+pub async fn get_nodes_in_box(
+    pool: &sqlx::PgPool,
+    columns: AdjacencyColumns,
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    max_lon: f64,
+) -> Result<Vec<RawNode>, io::Error> {
+    // planet_osm_nodes keeps coordinates as fixed-point 1e-7 degrees
+    let query = format!(
+        r#"
+        SELECT
+            planet_osm_nodes.id,
+            (planet_osm_nodes.lon / 1e7)::FLOAT8 AS lon,
+            (planet_osm_nodes.lat / 1e7)::FLOAT8 AS lat,
+            {}
+        FROM
+            planet_osm_nodes
+        JOIN
+            adjacent_nodes ON planet_osm_nodes.id = adjacent_nodes.id
+        WHERE planet_osm_nodes.lat BETWEEN $1 AND $2
+          AND planet_osm_nodes.lon BETWEEN $3 AND $4;
+    "#,
+        columns.select_list()
+    );
+
+    let nodes = sqlx::query_as::<_, RawNode>(&query)
+        .bind((min_lat * 1e7).floor() as i32)
+        .bind((max_lat * 1e7).ceil() as i32)
+        .bind((min_lon * 1e7).floor() as i32)
+        .bind((max_lon * 1e7).ceil() as i32)
+        .fetch_all(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    Ok(nodes)
+}
+
+// Function to fetch every routable node with its adjacency list in one pass
+pub async fn get_all_nodes(pool: &sqlx::PgPool, columns: AdjacencyColumns) -> Result<Vec<RawNode>, io::Error> {
+    let query = format!(
+        r#"
+        SELECT
+            planet_osm_nodes.id,
+            (planet_osm_nodes.lon / 1e7)::FLOAT8 AS lon,
+            (planet_osm_nodes.lat / 1e7)::FLOAT8 AS lat,
+            {}
+        FROM
+            planet_osm_nodes
+        JOIN
+            adjacent_nodes ON planet_osm_nodes.id = adjacent_nodes.id;
+    "#,
+        columns.select_list()
+    );
+
+    let nodes = sqlx::query_as::<_, RawNode>(&query)
+        .fetch_all(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    Ok(nodes)
+}
+
+// Function to fetch every way the routing graph was built from, with its tags
+pub async fn get_ways(pool: &sqlx::PgPool) -> Result<Vec<OsmWay>, io::Error> {
+    let query = r#"
+        SELECT
+            planet_osm_ways.id,
+            planet_osm_ways.nodes,
+            planet_osm_ways.tags
+        FROM
+            planet_osm_ways;
+    "#;
+
+    let ways = sqlx::query_as::<_, OsmWay>(query)
+        .fetch_all(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    Ok(ways)
+}
+
+// Function to fetch the raw hourly counts loaded by import-traffic
+pub async fn get_traffic_counts(pool: &sqlx::PgPool) -> Result<Vec<TrafficCount>, io::Error> {
+    let query = r#"
+        SELECT
+            segment_id, year, month, day, hour, volume, boro,
+            lat, lon, street, from_street, to_street, direction
+        FROM
+            traffic_counts
+        ORDER BY
+            segment_id, year, month, day, hour;
+    "#;
+
+    let counts = sqlx::query_as::<_, TrafficCount>(query)
+        .fetch_all(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    Ok(counts)
+}
+
+// Function to fetch every named point plus a label point inside every named area
+pub async fn get_places(pool: &sqlx::PgPool) -> Result<Vec<Place>, io::Error> {
+    // ST_PointOnSurface stays inside concave buildings where a centroid may not
+    let query = r#"
+        SELECT
+            osm_id,
+            name,
+            ST_Y(ST_Transform(way, 4326)) AS lat,
+            ST_X(ST_Transform(way, 4326)) AS lon
+        FROM
+            planet_osm_point
+        WHERE name IS NOT NULL
+        UNION ALL
+        SELECT
+            osm_id,
+            name,
+            ST_Y(ST_Transform(ST_PointOnSurface(way), 4326)) AS lat,
+            ST_X(ST_Transform(ST_PointOnSurface(way), 4326)) AS lon
+        FROM
+            planet_osm_polygon
+        WHERE name IS NOT NULL;
+    "#;
+
+    let places = sqlx::query_as::<_, Place>(query)
+        .fetch_all(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    Ok(places)
+}
+
+// Function to fetch the hourly traffic volumes matched onto graph edges
+pub async fn get_edge_traffic(pool: &sqlx::PgPool) -> Result<Vec<EdgeVolume>, io::Error> {
+    let query = r#"
+        SELECT
+            edge_traffic.source,
+            edge_traffic.target,
+            edge_traffic.hour,
+            edge_traffic.volume::FLOAT8 AS volume
+        FROM
+            edge_traffic;
+    "#;
+
+    let volumes = sqlx::query_as::<_, EdgeVolume>(query)
+        .fetch_all(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    Ok(volumes)
+}
+
+// Function to replace the edge_traffic table with freshly matched volumes
+pub async fn write_edge_traffic(pool: &sqlx::PgPool, volumes: &[EdgeVolume]) -> Result<(), io::Error> {
+    execute(pool, "DROP TABLE IF EXISTS edge_traffic;").await?;
+    execute(
+        pool,
+        r#"
+        CREATE TABLE edge_traffic (
+            source BIGINT NOT NULL,
+            target BIGINT NOT NULL,
+            hour INTEGER NOT NULL,
+            volume FLOAT8 NOT NULL
+        );
+    "#,
+    )
+    .await?;
+
+    for chunk in volumes.chunks(INSERT_CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("INSERT INTO edge_traffic (source, target, hour, volume) ");
+        builder.push_values(chunk, |mut row, volume| {
+            row.push_bind(volume.source)
+                .push_bind(volume.target)
+                .push_bind(volume.hour)
+                .push_bind(volume.volume);
+        });
+
+        builder
+            .build()
+            .execute(pool)
+            .await
+            .map_err(|err| io::Error::other(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+// Function to replace the traffic_segment_matches table, one row per matched edge
+pub async fn write_segment_matches(pool: &sqlx::PgPool, matches: &[SegmentMatch]) -> Result<(), io::Error> {
+    execute(pool, "DROP TABLE IF EXISTS traffic_segment_matches;").await?;
+    execute(
+        pool,
+        r#"
+        CREATE TABLE traffic_segment_matches (
+            segment_id INTEGER NOT NULL,
+            direction TEXT NOT NULL,
+            source BIGINT NOT NULL,
+            target BIGINT NOT NULL,
+            way_id BIGINT NOT NULL,
+            distance FLOAT8 NOT NULL,
+            name_similarity FLOAT8 NOT NULL,
+            score FLOAT8 NOT NULL,
+            confidence TEXT NOT NULL
+        );
+    "#,
+    )
+    .await?;
+
+    let rows: Vec<(&SegmentMatch, (i64, i64))> = matches
+        .iter()
+        .flat_map(|segment_match| segment_match.edges.iter().map(move |edge| (segment_match, *edge)))
+        .collect();
+    for chunk in rows.chunks(INSERT_CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO traffic_segment_matches \
+             (segment_id, direction, source, target, way_id, distance, name_similarity, score, confidence) ",
+        );
+        builder.push_values(chunk, |mut row, (segment_match, (source, target))| {
+            row.push_bind(segment_match.segment_id)
+                .push_bind(segment_match.direction.clone())
+                .push_bind(*source)
+                .push_bind(*target)
+                .push_bind(segment_match.way_id)
+                .push_bind(segment_match.distance)
+                .push_bind(segment_match.name_similarity)
+                .push_bind(segment_match.score)
+                .push_bind(format!("{:?}", segment_match.confidence).to_lowercase());
+        });
+
+        builder
+            .build()
+            .execute(pool)
+            .await
+            .map_err(|err| io::Error::other(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
 pub async fn query_node_by_coordinates(pool: &sqlx::PgPool,
     latitude: f64,
     longitude: f64) -> Result<Option<RawNode>, io::Error> {
@@ -115,78 +514,6 @@ fn find_closest_node(nodes:Vec<RawNode>, target_lat: f64, target_lon: f64) -> Op
 }
 
 
-/**
- * query by points
-
- SELECT
-    osm_id,
-    ST_X(ST_Transform(way, 4326)) AS longitude,
-    ST_Y(ST_Transform(way, 4326)) AS latitude,
-    name,
-    ST_Distance(ST_Transform(way, 4326), ST_SetSRID(ST_MakePoint(-74.6554635, 40.3463566), 4326)) AS distance
-FROM
-    planet_osm_point
-WHERE
-    ST_DWithin(
-        ST_Transform(way, 3857),  -- Use the correct SRID for the geometries
-        ST_Transform(ST_SetSRID(ST_MakePoint(-74.6554635, 40.3463566), 4326), 3857),  -- Transform the point to match the geometries' SRID
-        1 -- Distance in meters, ensure it aligns with the geometry SRID units
-    )
-ORDER BY
-    distance ASC;
-
- */
-pub async fn get_node_by_lat_lon(
-    pool: &sqlx::PgPool,
-    latitude: f64,
-    longitude: f64
-) -> Result<Option<RawNode>, io::Error> {
-    let tolerance = 5.0;
-
-    let query = r#"
-        WITH nearest_point AS (
-            SELECT
-                osm_id,
-                ST_X(ST_Transform(way, 4326)) AS longitude,
-                ST_Y(ST_Transform(way, 4326)) AS latitude
-            FROM
-                planet_osm_point
-            WHERE
-                ST_DWithin(
-                    ST_Transform(way, 3857),
-                    ST_Transform(ST_SetSRID(ST_MakePoint($1, $2), 4326), 3857),
-                    1000.0  -- Set your desired distance threshold in meters
-                )
-            ORDER BY
-                ST_Distance(ST_Transform(way, 4326), ST_SetSRID(ST_MakePoint($1, $2), 4326))
-            LIMIT 1
-        )
-        SELECT
-            n.id,
-            (n.lon / 1e7)::FLOAT8 AS lon,
-            (n.lat / 1e7)::FLOAT8 AS lat,
-            a.nodes AS adjacency_list
-        FROM
-            planet_osm_nodes n
-        JOIN
-            nearest_point np ON n.id = np.osm_id
-        JOIN
-            adjacent_nodes a ON a.id = np.osm_id;  -- Join with the adjacent_nodes table
-    "#;
-
-    let node = sqlx::query_as::<_, RawNode>(query)
-        .bind(longitude)
-        .bind(latitude)
-        .bind(tolerance)
-        .fetch_one(pool)  // This will fetch a single row
-        .await
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
-
-    Ok(Some(node))
-
-}
-
-
 
 /**
  * query adjacency nodes
@@ -225,4 +552,68 @@ pub async fn get_adjacent_nodes(pool: &sqlx::PgPool, osm_id: i64) -> Result<Vec<
 
     // Return the adjacency list
     Ok(nodes)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgConnectOptions;
+    use std::str::FromStr;
+
+    // Scratch tables go into their own schema of the database at DATABASE_URL,
+    // the tests are skipped when it is not set
+    async fn scratch_pool(schema: &str) -> Option<sqlx::PgPool> {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return None;
+        };
+        let admin = create_pool(&url).await.unwrap();
+        execute(&admin, &format!("DROP SCHEMA IF EXISTS {} CASCADE", schema)).await.unwrap();
+        execute(&admin, &format!("CREATE SCHEMA {}", schema)).await.unwrap();
+
+        let options = PgConnectOptions::from_str(&url).unwrap().options([("search_path", schema)]);
+        Some(PgPoolOptions::new().max_connections(1).connect_with(options).await.unwrap())
+    }
+
+    // adjacent_nodes as setup_psql.py creates it, with only id and nodes
+    #[tokio::test]
+    async fn decodes_rows_without_optional_columns() {
+        let schema = "test_adjacency_without_optional_columns";
+        let Some(pool) = scratch_pool(schema).await else {
+            return;
+        };
+        execute(&pool, "CREATE TABLE planet_osm_nodes (id BIGINT PRIMARY KEY, lat INTEGER NOT NULL, lon INTEGER NOT NULL)")
+            .await
+            .unwrap();
+        execute(&pool, "CREATE TABLE adjacent_nodes (id BIGINT PRIMARY KEY, nodes BIGINT[])")
+            .await
+            .unwrap();
+        execute(&pool, "INSERT INTO planet_osm_nodes VALUES (1, 403463566, -746554635), (2, 403464566, -746554635)")
+            .await
+            .unwrap();
+        execute(&pool, "INSERT INTO adjacent_nodes VALUES (1, '{2}'), (2, '{1}')")
+            .await
+            .unwrap();
+
+        let columns = AdjacencyColumns::detect(&pool).await.unwrap();
+        assert!(!columns.lengths && !columns.car_nodes && !columns.bike_nodes);
+
+        let mut nodes = get_nodes_by_ids(&pool, columns, &[1, 2]).await.unwrap();
+        nodes.sort_by_key(|node| node.id);
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].adjacency_list, vec![2]);
+        assert_eq!(nodes[0].adjacency_lengths, None);
+        assert_eq!(nodes[0].edge_length(TravelMode::Foot, 2), None);
+        assert_eq!(nodes[0].car_adjacency_list, None);
+        assert_eq!(nodes[0].bike_adjacency_list, None);
+        assert!((nodes[0].lat - 40.3463566).abs() < 1e-9);
+        // Without directed lists every mode falls back to the walking neighbors
+        assert_eq!(nodes[0].adjacency(TravelMode::Car), &[2]);
+        assert_eq!(get_node_by_id(&pool, columns, 2).await.unwrap().adjacency_list, vec![1]);
+        assert_eq!(get_all_nodes(&pool, columns).await.unwrap().len(), 2);
+        assert_eq!(get_nodes_in_box(&pool, columns, 40.0, -75.0, 41.0, -74.0).await.unwrap().len(), 2);
+
+        pool.close().await;
+        let admin = create_pool(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+        execute(&admin, &format!("DROP SCHEMA {} CASCADE", schema)).await.unwrap();
+    }
+}
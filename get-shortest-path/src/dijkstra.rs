@@ -1,46 +1,76 @@
-use crate::database; // Import the database module from the root
+use crate::algorithm::Algorithm;
+use crate::alt::LandmarkTables;
+use crate::bidirectional;
+use crate::ch::ContractionHierarchy;
 use crate::database::RawNode;
+use crate::mode::TravelMode;
+use crate::snap::is_virtual;
+use crate::source::GraphSource;
 
-use std::collections::{BinaryHeap, HashMap};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::cmp::Ordering;
-use tokio::time::{timeout, Duration, Instant};
-
-use lambda_runtime::tracing::{info, debug, error};
+use tokio::time::error::Elapsed;
+use tokio::time::{timeout_at, Duration, Instant};
+
+use lambda_runtime::tracing::{info, error};
+
+// Haversine on a sphere overestimates Vincenty lengths by up to ~0.15% north-south,
+// scaling it down keeps the A* heuristic admissible
+const HEURISTIC_SCALE: f64 = 0.995;
+
+// Lambda timeout limit minus buffer, searches give up and return what they have
+pub(crate) const SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of one point-to-point search.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SearchResult {
+    pub path: Vec<[f64; 2]>,
+    pub node_ids: Vec<i64>,
+    pub distance: f64,
+    // Estimated travel time in seconds
+    pub duration: f64,
+    pub nodes_expanded: usize,
+    pub timed_out: bool,
+}
 
+// Heap entry, `node` is the node itself or just its id
 #[derive(Debug)]
-struct State {
-    cost: f64,
-    node: RawNode,
+pub(crate) struct State<N = RawNode> {
+    pub(crate) cost: f64,
+    pub(crate) node: N,
 }
 
-impl Ord for State {
+impl<N> Ord for State<N> {
     fn cmp(&self, other: &Self) -> Ordering {
         other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
     }
 }
 
-impl PartialOrd for State {
+impl<N> PartialOrd for State<N> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.cost.partial_cmp(&other.cost)
+        Some(self.cmp(other))
     }
 }
 
-impl PartialEq for State {
+impl<N> PartialEq for State<N> {
     fn eq(&self, other: &Self) -> bool {
         self.cost == other.cost
     }
 }
 
-impl Eq for State {}
+impl<N> Eq for State<N> {}
 
-fn reconstruct_path(predecessors: &HashMap<i64, i64>, start: i64, end: i64, nodes: &HashMap<i64, RawNode>) -> Vec<[f64; 2]> {
+// Walk the predecessors back from `end`, returning the node ids in travel order
+pub(crate) fn reconstruct_node_ids(predecessors: &HashMap<i64, i64>, start: i64, end: i64) -> Vec<i64> {
     let mut path = Vec::new();
     let mut current = end;
 
     while current != start {
-        path.push(current.clone());
+        path.push(current);
         if let Some(pred) = predecessors.get(&current) {
-            current = pred.clone();
+            current = *pred;
         } else {
             return Vec::new(); // Path not found
         }
@@ -48,13 +78,142 @@ fn reconstruct_path(predecessors: &HashMap<i64, i64>, start: i64, end: i64, node
 
     path.push(start);
     path.reverse();
+    path
+}
 
-    path.iter()
-        .map(|node_id| {
-            let node = nodes.get(node_id).unwrap();
-            [node.lon, node.lat] // Return as 2D array [longitude, latitude]
-        })
-        .collect()
+// Nodes a search has reached. Backends holding the whole graph in memory lend their
+// own nodes, from any other backend nodes are fetched once per search and kept here.
+pub(crate) struct NodeCache<'a, S> {
+    source: &'a S,
+    fetched: HashMap<i64, RawNode>,
+    missing: HashSet<i64>, // Ids the backend has no row for
+    query_count: usize,
+    fetched_count: usize, // Nodes returned across all queries
+    query_times: Vec<Duration>,
+}
+
+impl<'a, S: GraphSource> NodeCache<'a, S> {
+    // `known` are nodes the search already holds, its source and destination
+    pub(crate) fn new(source: &'a S, known: &[&RawNode]) -> NodeCache<'a, S> {
+        let mut cache = NodeCache {
+            source,
+            fetched: HashMap::new(),
+            missing: HashSet::new(),
+            query_count: 0,
+            fetched_count: 0,
+            query_times: Vec::new(),
+        };
+        if !source.is_resident() {
+            cache.fetched.extend(known.iter().map(|node| (node.id, (*node).clone())));
+        }
+        cache
+    }
+
+    pub(crate) fn get(&self, id: i64) -> Option<Cow<'_, RawNode>> {
+        if self.source.is_resident() {
+            return self.source.borrow_node(id);
+        }
+        self.fetched.get(&id).map(Cow::Borrowed)
+    }
+
+    pub(crate) fn query_count(&self) -> usize {
+        self.query_count
+    }
+
+    // Fetch whichever of `ids` are not known yet. The query gets until `deadline`, so a
+    // hung database connection cannot hold the search past the Lambda timeout.
+    pub(crate) async fn fetch(&mut self, ids: impl IntoIterator<Item = i64>, deadline: Instant) -> Result<(), Elapsed> {
+        if self.source.is_resident() {
+            return Ok(());
+        }
+        let mut unseen_ids: Vec<i64> = ids
+            .into_iter()
+            .filter(|id| !self.fetched.contains_key(id) && !self.missing.contains(id))
+            .collect();
+        if unseen_ids.is_empty() {
+            return Ok(());
+        }
+        unseen_ids.sort_unstable();
+        unseen_ids.dedup();
+
+        let query_start = Instant::now();
+        self.query_count += 1;
+        let result = timeout_at(deadline, self.source.get_neighbors(&unseen_ids)).await;
+        self.query_times.push(query_start.elapsed());
+        match result? {
+            Ok(fetched) => {
+                self.fetched_count += fetched.len();
+                for fetched_node in fetched {
+                    self.fetched.insert(fetched_node.id, fetched_node);
+                }
+                // Remember ids the backend did not return so they are never requested again
+                let fetched = &self.fetched;
+                self.missing.extend(unseen_ids.into_iter().filter(|id| !fetched.contains_key(id)));
+            }
+            Err(err) => {
+                error!("Error fetching {} nodes: {:?}", unseen_ids.len(), err);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn log_statistics(&self) {
+        if self.query_times.is_empty() {
+            return;
+        }
+        let min_query_time = self.query_times.iter().min().unwrap();
+        let max_query_time = self.query_times.iter().max().unwrap();
+        let avg_query_time: f64 =
+            self.query_times.iter().map(|d| d.as_secs_f64()).sum::<f64>() / self.query_times.len() as f64;
+
+        info!(
+            "Query statistics - {} queries, {} nodes fetched. Min: {:?}, Max: {:?}, Avg: {:.4} seconds",
+            self.query_count, self.fetched_count, min_query_time, max_query_time, avg_query_time
+        );
+    }
+}
+
+// Expand a node id path into coordinates, following every edge's stored shape
+pub(crate) fn path_coordinates<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    node_ids: &[i64],
+    nodes: &NodeCache<S>,
+) -> Vec<[f64; 2]> {
+    let Some(start_node) = node_ids.first().and_then(|id| nodes.get(*id)) else {
+        return Vec::new();
+    };
+    let mut coordinates = vec![[start_node.lon, start_node.lat]]; // Return as 2D array [longitude, latitude]
+
+    for pair in node_ids.windows(2) {
+        let node = nodes.get(pair[1]).unwrap();
+        match source.edge_geometry(mode, pair[0], pair[1]) {
+            // The first point is the previous node, already in the path
+            Some(geometry) if geometry.len() > 2 => {
+                coordinates.extend_from_slice(&geometry[1..geometry.len() - 1]);
+                coordinates.push([node.lon, node.lat]);
+            }
+            _ => coordinates.push([node.lon, node.lat]),
+        }
+    }
+
+    coordinates
+}
+
+// Fetch the nodes of a path found without loading them, then expand it into coordinates
+pub(crate) async fn fetch_path_coordinates<S: GraphSource>(source: &S, mode: TravelMode, node_ids: &[i64]) -> Vec<[f64; 2]> {
+    let mut nodes = NodeCache::new(source, &[]);
+    let deadline = Instant::now() + SEARCH_TIMEOUT;
+    if nodes.fetch(node_ids.iter().copied(), deadline).await.is_err() {
+        error!("Fetching {} path nodes timed out", node_ids.len());
+        return Vec::new();
+    }
+    if node_ids.iter().any(|id| nodes.get(*id).is_none()) {
+        error!("Path references nodes missing from the graph");
+        return Vec::new();
+    }
+
+    path_coordinates(source, mode, node_ids, &nodes)
 }
 
 fn get_distance(node_a: &RawNode, node_b: &RawNode) -> f64 {
@@ -64,122 +223,388 @@ fn get_distance(node_a: &RawNode, node_b: &RawNode) -> f64 {
     node_a_location.distance_to(&node_b_location).unwrap().meters()
 }
 
+// Stored length of the edge `mode` travels, tables without one fall back to straight-line distance
+pub(crate) fn edge_weight(mode: TravelMode, node: &RawNode, next_node: &RawNode) -> f64 {
+    node.edge_length(mode, next_node.id)
+        .unwrap_or_else(|| get_distance(node, next_node))
+}
+
+// Great-circle lower bound on the remaining distance to `dest_node`
+pub(crate) fn great_circle_heuristic(node: &RawNode, dest_node: &RawNode) -> f64 {
+    let node_location = geoutils::Location::new(node.lat, node.lon);
+    let dest_location = geoutils::Location::new(dest_node.lat, dest_node.lon);
+
+    node_location.haversine_distance_to(&dest_location).meters() * HEURISTIC_SCALE
+}
+
+// Run the search selected by `algorithm`
+pub async fn shortest_path<S: GraphSource>(
+    source: &S,
+    algorithm: Algorithm,
+    mode: TravelMode,
+    src_node: RawNode,
+    dest_node: RawNode,
+) -> SearchResult {
+    match algorithm {
+        Algorithm::Dijkstra => dijkstra(source, mode, src_node, dest_node).await,
+        Algorithm::AStar => astar(source, mode, src_node, dest_node).await,
+        Algorithm::Bidirectional => bidirectional::bidirectional_dijkstra(source, mode, src_node, dest_node).await,
+        Algorithm::ContractionHierarchy => match source.contraction_hierarchy(mode) {
+            Some(hierarchy) => contraction_hierarchy(source, hierarchy, src_node, dest_node).await,
+            None => {
+                error!("No contraction hierarchy loaded for {:?}, falling back to bidirectional Dijkstra", mode);
+                bidirectional::bidirectional_dijkstra(source, mode, src_node, dest_node).await
+            }
+        },
+        Algorithm::Alt => match source.landmark_tables(mode) {
+            Some(tables) => alt(source, tables, src_node, dest_node).await,
+            None => {
+                error!("No landmark tables loaded for {:?}, falling back to A*", mode);
+                astar(source, mode, src_node, dest_node).await
+            }
+        },
+    }
+}
+
+// Where a search endpoint enters the hierarchy: the node itself, or for a virtual node
+// the real ends of the edge it splits with the stored distance to each
+fn hierarchy_access(mode: TravelMode, node: &RawNode, neighbor_ids: &[i64]) -> Vec<(i64, f64)> {
+    if !is_virtual(node.id) {
+        return vec![(node.id, 0.0)];
+    }
+    neighbor_ids
+        .iter()
+        .filter(|id| !is_virtual(**id))
+        .filter_map(|id| Some((*id, node.edge_length(mode, *id)?)))
+        .collect()
+}
+
+pub async fn contraction_hierarchy<S: GraphSource>(
+    source: &S,
+    hierarchy: &ContractionHierarchy,
+    src_node: RawNode,
+    dest_node: RawNode,
+) -> SearchResult {
+    let mode = hierarchy.mode();
+    info!("Contraction hierarchy query started for {:?}", mode);
+    let start_time = Instant::now();
+
+    // Two points snapped onto the same edge may reach each other without leaving it
+    let mut best: Option<(f64, Vec<i64>)> = None;
+    if src_node.id != dest_node.id && src_node.adjacency(mode).contains(&dest_node.id) {
+        best = Some((edge_weight(mode, &src_node, &dest_node), vec![src_node.id, dest_node.id]));
+    }
+
+    let mut nodes_expanded = 0;
+    for (src_id, src_offset) in hierarchy_access(mode, &src_node, src_node.adjacency(mode)) {
+        for (dest_id, dest_offset) in hierarchy_access(mode, &dest_node, dest_node.reverse_adjacency(mode)) {
+            let Some(ch_path) = hierarchy.shortest_path(src_id, dest_id) else { continue };
+            nodes_expanded += ch_path.nodes_expanded;
+            let distance = src_offset + ch_path.distance + dest_offset;
+            if best.as_ref().map(|(best_distance, _)| distance < *best_distance).unwrap_or(true) {
+                let mut node_ids = ch_path.node_ids;
+                if is_virtual(src_node.id) {
+                    node_ids.insert(0, src_node.id);
+                }
+                if is_virtual(dest_node.id) {
+                    node_ids.push(dest_node.id);
+                }
+                best = Some((distance, node_ids));
+            }
+        }
+    }
+
+    let Some((distance, node_ids)) = best else {
+        info!("No path found from node {} to node {}", src_node.id, dest_node.id);
+        return SearchResult::default();
+    };
+    info!(
+        "Contraction hierarchy query completed in {:?}. Nodes expanded: {}",
+        start_time.elapsed(),
+        nodes_expanded
+    );
+
+    SearchResult {
+        path: fetch_path_coordinates(source, mode, &node_ids).await,
+        node_ids,
+        distance,
+        duration: distance / mode.speed_mps(),
+        nodes_expanded,
+        timed_out: false,
+    }
+}
+
+pub async fn dijkstra<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    src_node: RawNode,
+    dest_node: RawNode,
+) -> SearchResult {
+    info!("Dijkstra execution started for {:?}", mode);
+    search(source, mode, src_node, dest_node, |_| 0.0, |from, to| edge_weight(mode, from, to)).await
+}
+
+// A*: Dijkstra ordered by cost so far plus a great-circle estimate of the rest
+pub async fn astar<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    src_node: RawNode,
+    dest_node: RawNode,
+) -> SearchResult {
+    info!("A* execution started for {:?}", mode);
+    let target = dest_node.clone();
+    search(
+        source,
+        mode,
+        src_node,
+        dest_node,
+        |node| great_circle_heuristic(node, &target),
+        |from, to| edge_weight(mode, from, to),
+    )
+    .await
+}
 
+// ALT: A* with triangle-inequality bounds from the landmark distance tables
+pub async fn alt<S: GraphSource>(
+    source: &S,
+    tables: &LandmarkTables,
+    src_node: RawNode,
+    dest_node: RawNode,
+) -> SearchResult {
+    let mode = tables.mode();
+    info!("ALT execution started for {:?} with {} landmarks", mode, tables.landmarks().len());
+    // The tables only know real nodes. Every path to a virtual destination enters its
+    // edge through one of the real ends, so the smallest bound to those still holds.
+    let target_ids: Vec<i64> = if is_virtual(dest_node.id) {
+        dest_node
+            .reverse_adjacency(mode)
+            .iter()
+            .copied()
+            .filter(|id| !is_virtual(*id))
+            .collect()
+    } else {
+        vec![dest_node.id]
+    };
+    search(
+        source,
+        mode,
+        src_node,
+        dest_node,
+        |node| {
+            target_ids
+                .iter()
+                .map(|target_id| tables.lower_bound(node.id, *target_id))
+                .reduce(f64::min)
+                .unwrap_or(0.0)
+        },
+        |from, to| edge_weight(mode, from, to),
+    )
+    .await
+}
+
+// Point-to-point search over the shared exploration loop
+pub(crate) async fn search<S, H, W>(
+    source: &S,
+    mode: TravelMode,
+    src_node: RawNode,
+    dest_node: RawNode,
+    heuristic: H,
+    weight: W,
+) -> SearchResult
+where
+    S: GraphSource,
+    H: Fn(&RawNode) -> f64,
+    W: Fn(&RawNode, &RawNode) -> f64,
+{
+    let exploration = explore(
+        source,
+        mode,
+        &src_node,
+        &[dest_node.id],
+        f64::MAX,
+        heuristic,
+        |from: &RawNode, to: &RawNode, _: f64| weight(from, to),
+    )
+    .await;
+    let Exploration {
+        distances,
+        predecessors,
+        nodes,
+        expanded_count,
+        timed_out,
+        last_computed_node,
+    } = exploration;
+
+    if !timed_out && !distances.contains_key(&dest_node.id) {
+        info!("No path found from node {} to node {}", src_node.id, dest_node.id);
+    }
 
+    // On timeout return the path to the last computed node
+    let end_node_id = if timed_out { last_computed_node } else { dest_node.id };
+    let node_ids = match distances.get(&end_node_id) {
+        Some(_) => reconstruct_node_ids(&predecessors, src_node.id, end_node_id),
+        None => Vec::new(),
+    };
+
+    let distance = *distances.get(&end_node_id).unwrap_or(&0.0);
+    SearchResult {
+        path: path_coordinates(source, mode, &node_ids, &nodes),
+        distance,
+        duration: distance / mode.speed_mps(),
+        node_ids,
+        nodes_expanded: expanded_count,
+        timed_out,
+    }
+}
 
-pub async fn dijkstra(pool: &sqlx::PgPool, src_node: RawNode, dest_node: RawNode) -> Vec<[f64; 2]> {
-    info!("Dijkstra execution started");
+/// Everything a finished exploration knows about the nodes it reached.
+pub(crate) struct Exploration<'a, S> {
+    pub(crate) distances: HashMap<i64, f64>,
+    pub(crate) predecessors: HashMap<i64, i64>,
+    pub(crate) nodes: NodeCache<'a, S>,
+    pub(crate) expanded_count: usize,
+    pub(crate) timed_out: bool,
+    // Last node expanded, where a timed out search got to
+    pub(crate) last_computed_node: i64,
+}
 
-    let timeout_duration = Duration::from_secs(10); // Set Lambda timeout limit minus buffer
+// Shared search loop. The heap is ordered by cost so far plus `heuristic`; a zero
+// heuristic is plain Dijkstra, a consistent lower bound on the remaining distance is A*.
+// `weight` prices each edge given the cost already spent reaching it, normally just
+// `edge_weight`; with a heuristic it must never go below that or the bounds break.
+// The loop stops once every node in `targets` has been reached (never for no targets),
+// or once the smallest heap cost exceeds `max_distance`.
+pub(crate) async fn explore<'a, S, H, W>(
+    source: &'a S,
+    mode: TravelMode,
+    src_node: &RawNode,
+    targets: &[i64],
+    max_distance: f64,
+    heuristic: H,
+    weight: W,
+) -> Exploration<'a, S>
+where
+    S: GraphSource,
+    H: Fn(&RawNode) -> f64,
+    W: Fn(&RawNode, &RawNode, f64) -> f64,
+{
     let start_time = Instant::now(); // Record start time
+    let deadline = start_time + SEARCH_TIMEOUT;
+    let frontier_batch_size = source.frontier_batch_size().max(1);
 
     let mut distances: HashMap<i64, f64> = HashMap::new();
     let mut predecessors: HashMap<i64, i64> = HashMap::new();
-    let mut nodes: HashMap<i64, RawNode> = HashMap::new();
-    let mut heap = BinaryHeap::new();
+    let mut nodes = NodeCache::new(source, &[src_node]);
+    let mut heap: BinaryHeap<State<i64>> = BinaryHeap::new();
 
-    let mut query_count = 0; // Query counter
-    let mut query_times = Vec::new(); // To store query times
+    let mut remaining: HashSet<i64> = targets.iter().copied().collect(); // Targets not reached yet
+    let mut expanded_count = 0; // Nodes popped and expanded
 
     distances.insert(src_node.id, 0.0);
     heap.push(State {
-        cost: 0.0,
-        node: src_node.clone(),
+        cost: heuristic(src_node),
+        node: src_node.id,
     });
-    nodes.insert(src_node.id, src_node.clone());
 
-    let mut best_path = Vec::new(); // Variable to track the best path found so far
-    let mut last_computed_node = src_node.clone(); // Track the last computed node
+    let mut last_computed_node = src_node.id; // Track the last computed node
+    let mut timed_out = false;
 
-    let dijkstra_result = timeout(timeout_duration, async {
-        while let Some(State { cost, node }) = heap.pop() {
-            // Check if we have reached the destination node
-            if node.id == dest_node.id {
-                best_path = reconstruct_path(&predecessors, src_node.id, dest_node.id, &nodes);
-                break;
-            }
+    // Heap cost is distance + heuristic, so stale entries cost more than that sum
+    let is_stale = |state: &State<i64>, nodes: &NodeCache<S>, distances: &HashMap<i64, f64>| match nodes.get(state.node) {
+        Some(node) => state.cost > *distances.get(&state.node).unwrap_or(&f64::MAX) + heuristic(&node),
+        None => true,
+    };
 
-            if cost > *distances.get(&node.id).unwrap_or(&f64::MAX) {
-                continue;
-            }
+    while let Some(state) = heap.pop() {
+        // Check if we have reached the last target or spent the budget
+        if (remaining.remove(&state.node) && remaining.is_empty()) || state.cost > max_distance {
+            break;
+        }
+        if is_stale(&state, &nodes, &distances) {
+            continue;
+        }
 
-            // Keep track of the last node processed before timeout
-            last_computed_node = node.clone();
-
-            // Process adjacent nodes
-            for next_node_id in node.adjacency_list.clone() {
-                let query_start = Instant::now(); // Start timing the query
-                query_count += 1; // Increment query counter
-
-                let next_node = match database::get_node_by_id(&pool, next_node_id).await {
-                    Ok(node) => node,
-                    Err(err) => {
-                        error!("Error fetching node with ID {}: {:?}", next_node_id, err);
-                        continue;
-                    }
-                };
-                let query_duration = query_start.elapsed(); // Calculate query time
-                query_times.push(query_duration);
-
-                let weight = get_distance(&node, &next_node);
-                let next_cost = cost + weight;
-
-                if next_cost < *distances.get(&next_node.id).unwrap_or(&f64::MAX) {
-                    distances.insert(next_node.id, next_cost);
-                    predecessors.insert(next_node.id, node.id);
-                    heap.push(State {
-                        cost: next_cost,
-                        node: next_node.clone(),
-                    });
-                    nodes.insert(next_node.id, next_node);
-                }
+        if start_time.elapsed() > SEARCH_TIMEOUT {
+            timed_out = true;
+            break;
+        }
+
+        // Keep track of the last node processed before timeout
+        last_computed_node = state.node;
+        expanded_count += 1;
+
+        // Pull a few more frontier nodes so their neighbors share this round-trip
+        let mut frontier = Vec::new();
+        while frontier.len() < frontier_batch_size - 1 {
+            match heap.pop() {
+                Some(next) if is_stale(&next, &nodes, &distances) => continue,
+                Some(next) => frontier.push(next),
+                None => break,
             }
         }
 
-        // If path was found, return it, else return empty Vec
-        if distances.contains_key(&dest_node.id) {
-            best_path = reconstruct_path(&predecessors, src_node.id, dest_node.id, &nodes);
+        if !source.is_resident() {
+            let neighbor_ids: Vec<i64> = std::iter::once(state.node)
+                .chain(frontier.iter().map(|next| next.node))
+                .filter_map(|id| nodes.get(id))
+                .flat_map(|node| node.adjacency(mode).to_vec())
+                .collect();
+            if nodes.fetch(neighbor_ids, deadline).await.is_err() {
+                timed_out = true;
+                break;
+            }
         }
+        // Frontier nodes are expanded later in their normal heap order
+        heap.extend(frontier);
+
+        // Process adjacent nodes
+        let node = nodes.get(state.node).unwrap();
+        let distance = distances[&node.id];
+        for next_node_id in node.adjacency(mode) {
+            let next_node = match nodes.get(*next_node_id) {
+                Some(next_node) => next_node,
+                None => {
+                    error!("Node with ID {} could not be fetched", next_node_id);
+                    continue;
+                }
+            };
 
-        Some(best_path)
-    })
-    .await;
+            let next_cost = distance + weight(&node, &next_node, distance);
+
+            if next_cost < *distances.get(&next_node.id).unwrap_or(&f64::MAX) {
+                distances.insert(next_node.id, next_cost);
+                predecessors.insert(next_node.id, node.id);
+                heap.push(State {
+                    cost: next_cost + heuristic(&next_node),
+                    node: next_node.id,
+                });
+            }
+        }
+    }
 
     // Log summary statistics
-    let elapsed_time = start_time.elapsed();
     info!(
-        "Dijkstra execution completed in {:?}. Total queries: {}",
-        elapsed_time, query_count
+        "Search completed in {:?}. Nodes expanded: {}, total queries: {}",
+        start_time.elapsed(),
+        expanded_count,
+        nodes.query_count()
     );
+    nodes.log_statistics();
 
-    if !query_times.is_empty() {
-        let min_query_time = query_times.iter().min().unwrap();
-        let max_query_time = query_times.iter().max().unwrap();
-        let avg_query_time: f64 = query_times.iter().map(|d| d.as_secs_f64()).sum::<f64>() / query_times.len() as f64;
-
-        info!(
-            "Query statistics - Min: {:?}, Max: {:?}, Avg: {:.4} seconds",
-            min_query_time, max_query_time, avg_query_time
+    if timed_out {
+        error!(
+            "Search timed out after {:?}. Queries executed: {}",
+            SEARCH_TIMEOUT,
+            nodes.query_count()
         );
     }
 
-    match dijkstra_result {
-        Ok(Some(path)) => path,
-        Ok(None) => {
-            info!(
-                "No path found from node {} to node {}. Execution time: {:?}",
-                src_node.id, dest_node.id, elapsed_time
-            );
-            Vec::new()
-        }
-        Err(_) => {
-            error!(
-                "Dijkstra function timed out after {:?}. Queries executed: {}",
-                timeout_duration, query_count
-            );
-            // Return the path to the last computed node
-            let path_to_last_node = reconstruct_path(&predecessors, src_node.id, last_computed_node.id, &nodes);
-            path_to_last_node
-        }
+    Exploration {
+        distances,
+        predecessors,
+        nodes,
+        expanded_count,
+        timed_out,
+        last_computed_node,
     }
 }
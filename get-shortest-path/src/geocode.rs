@@ -0,0 +1,278 @@
+// Place-name lookup for waypoints given by name. Names come from planet_osm_point and
+// the named areas of planet_osm_polygon. Matching ignores case and punctuation and
+// scores trigram overlap, so a typo like "East Pine Hall" still finds East Pyne Hall.
+// When no single place clearly wins, the caller gets the best candidates instead.
+
+use crate::snap::local_distance;
+use crate::ways::{parse_dict_line, Literal};
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+// Below this a candidate is not worth offering at all
+const MIN_SCORE: f64 = 0.45;
+// A match this good is taken when the runner-up trails it by SCORE_MARGIN
+const ACCEPT_SCORE: f64 = 0.65;
+const SCORE_MARGIN: f64 = 0.1;
+// Candidates returned for an ambiguous name
+const MAX_CANDIDATES: usize = 5;
+// A point and the area around it often carry the same name, keep only one
+const DUPLICATE_METERS: f64 = 150.0;
+
+/// A named point or the label point of a named area.
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct Place {
+    pub osm_id: i64,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// A place with how well its name matches the query, 1.0 for an exact match.
+#[derive(Clone, Debug, Serialize)]
+pub struct Candidate {
+    #[serde(flatten)]
+    pub place: Place,
+    pub score: f64,
+}
+
+/// Outcome of looking up one name.
+#[derive(Clone, Debug)]
+pub enum Resolution {
+    Found(Place),
+    /// Several places fit about equally well, best first.
+    Ambiguous(Vec<Candidate>),
+    NotFound,
+}
+
+// Lowercase words of letters and digits only
+fn normalize(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Padded so word starts and ends count as much as the middle
+fn trigrams(normalized: &str) -> HashSet<[char; 3]> {
+    let padded: Vec<char> = format!("  {} ", normalized).chars().collect();
+    padded.windows(3).map(|window| [window[0], window[1], window[2]]).collect()
+}
+
+// Whether the words of `query` appear in `name` as a consecutive run
+fn contains_words(name: &str, query: &str) -> bool {
+    let name_words: Vec<&str> = name.split_whitespace().collect();
+    let query_words: Vec<&str> = query.split_whitespace().collect();
+    !query_words.is_empty() && name_words.windows(query_words.len()).any(|window| window == query_words.as_slice())
+}
+
+/// Places indexed by normalized name.
+#[derive(Debug, Default)]
+pub struct Geocoder {
+    places: Vec<Place>,
+    names: Vec<String>,
+    name_trigrams: Vec<HashSet<[char; 3]>>,
+}
+
+impl Geocoder {
+    pub fn new(places: Vec<Place>) -> Geocoder {
+        let mut geocoder = Geocoder::default();
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        for place in places {
+            let name = normalize(&place.name);
+            if name.is_empty() {
+                continue;
+            }
+            let same_name = by_name.entry(name.clone()).or_default();
+            let duplicate = same_name.iter().any(|index| {
+                let other = &geocoder.places[*index];
+                local_distance(place.lat, place.lon, other.lat, other.lon) < DUPLICATE_METERS
+            });
+            if duplicate {
+                continue;
+            }
+            same_name.push(geocoder.places.len());
+            geocoder.name_trigrams.push(trigrams(&name));
+            geocoder.names.push(name);
+            geocoder.places.push(place);
+        }
+        geocoder
+    }
+
+    pub fn place_count(&self) -> usize {
+        self.places.len()
+    }
+
+    // Exact names score 1, a whole-word part of a longer name 0.8 and up, anything else
+    // the Dice coefficient of the two trigram sets
+    fn score(&self, index: usize, query: &str, query_trigrams: &HashSet<[char; 3]>) -> f64 {
+        let name = &self.names[index];
+        if name == query {
+            return 1.0;
+        }
+        if contains_words(name, query) {
+            return 0.8 + 0.15 * query.len() as f64 / name.len() as f64;
+        }
+        let name_trigrams = &self.name_trigrams[index];
+        let shared = name_trigrams.intersection(query_trigrams).count();
+        2.0 * shared as f64 / (name_trigrams.len() + query_trigrams.len()) as f64
+    }
+
+    /// Up to `limit` places whose names resemble `query`, best first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<Candidate> {
+        let query = normalize(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let query_trigrams = trigrams(&query);
+
+        let mut candidates: Vec<Candidate> = (0..self.places.len())
+            .map(|index| (index, self.score(index, &query, &query_trigrams)))
+            .filter(|(_, score)| *score >= MIN_SCORE)
+            .map(|(index, score)| Candidate {
+                place: self.places[index].clone(),
+                score,
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates.truncate(limit);
+        candidates
+    }
+
+    /// Turn a name into one place: an only exact match, or a strong match well ahead of
+    /// the rest. Equally good matches (two cafes of one chain) come back as candidates.
+    pub fn resolve(&self, query: &str) -> Resolution {
+        let mut candidates = self.search(query, MAX_CANDIDATES);
+        let (best, runner_up) = match candidates.as_slice() {
+            [] => return Resolution::NotFound,
+            [best] => (best.score, 0.0),
+            [best, second, ..] => (best.score, second.score),
+        };
+        let only_exact = best == 1.0 && runner_up < 1.0;
+        if only_exact || (best >= ACCEPT_SCORE && best - runner_up >= SCORE_MARGIN) {
+            return Resolution::Found(candidates.swap_remove(0).place);
+        }
+        Resolution::Ambiguous(candidates)
+    }
+}
+
+// Named nodes from output_nodes.txt, the CSV backend's stand-in for planet_osm_point
+fn parse_place_line(line: &str) -> Option<Place> {
+    let mut place = Place {
+        osm_id: 0,
+        name: String::new(),
+        lat: f64::NAN,
+        lon: f64::NAN,
+    };
+    for (key, value) in parse_dict_line(line)? {
+        match (key.as_str(), value) {
+            ("osm_id", Literal::Str(id)) => place.osm_id = id.parse().ok()?,
+            ("latitude", Literal::Bare(lat)) => place.lat = lat.parse().ok()?,
+            ("longitude", Literal::Bare(lon)) => place.lon = lon.parse().ok()?,
+            ("tags", Literal::Dict(tags)) => {
+                place.name = tags.into_iter().find_map(|(key, value)| match (key.as_str(), value) {
+                    ("name", Literal::Str(name)) => Some(name),
+                    _ => None,
+                })?;
+            }
+            _ => {}
+        }
+    }
+    (!place.name.is_empty() && place.lat.is_finite() && place.lon.is_finite()).then_some(place)
+}
+
+/// Read the named nodes dumped to output_nodes.txt. Unnamed nodes are skipped.
+pub fn read_places_txt(path: &str) -> Result<Vec<Place>, io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut places = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('{') && line.contains("'name'") {
+            places.extend(parse_place_line(&line));
+        }
+    }
+    Ok(places)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place(osm_id: i64, name: &str, lat: f64, lon: f64) -> Place {
+        Place { osm_id, name: name.to_string(), lat, lon }
+    }
+
+    fn campus() -> Geocoder {
+        Geocoder::new(vec![
+            place(1, "East Pyne Hall", 40.3490, -74.6594),
+            place(2, "Nassau Hall", 40.3487, -74.6593),
+            place(3, "Firestone Library", 40.3496, -74.6573),
+            place(4, "Starbucks", 40.3502, -74.6601),
+            place(5, "Starbucks", 40.3571, -74.6672),
+            // The same cafe as a point and as an area, 50 m apart
+            place(6, "Starbucks", 40.3506, -74.6601),
+        ])
+    }
+
+    fn found_id(resolution: Resolution) -> Option<i64> {
+        match resolution {
+            Resolution::Found(place) => Some(place.osm_id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn nearby_places_of_one_name_are_indexed_once() {
+        assert_eq!(campus().place_count(), 5);
+    }
+
+    #[test]
+    fn exact_and_strong_matches_resolve() {
+        let geocoder = campus();
+        // Case and punctuation don't matter
+        assert_eq!(found_id(geocoder.resolve("NASSAU HALL!")), Some(2));
+        // A typo still wins by a wide margin
+        assert_eq!(found_id(geocoder.resolve("East Pine Hall")), Some(1));
+        // Part of a longer name, whole words only
+        assert_eq!(found_id(geocoder.resolve("firestone")), Some(3));
+    }
+
+    #[test]
+    fn close_scores_are_ambiguous() {
+        let geocoder = campus();
+        // Two cafes of one chain, both exact
+        let Resolution::Ambiguous(candidates) = geocoder.resolve("Starbucks") else {
+            panic!("two exact matches must be ambiguous");
+        };
+        let mut ids: Vec<i64> = candidates.iter().map(|candidate| candidate.place.osm_id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, [4, 5]);
+        assert!(candidates.iter().all(|candidate| candidate.score == 1.0));
+
+        // Word matches in two names within SCORE_MARGIN of each other, best first
+        let Resolution::Ambiguous(candidates) = geocoder.resolve("hall") else {
+            panic!("two similar word matches must be ambiguous");
+        };
+        let ids: Vec<i64> = candidates.iter().map(|candidate| candidate.place.osm_id).collect();
+        assert_eq!(ids, [2, 1]);
+        assert!(candidates[0].score - candidates[1].score < SCORE_MARGIN);
+        assert!(candidates.iter().all(|candidate| candidate.score >= ACCEPT_SCORE));
+    }
+
+    #[test]
+    fn weak_matches_are_not_found() {
+        let geocoder = campus();
+        assert!(matches!(geocoder.resolve("Zzyzx Road"), Resolution::NotFound));
+        assert!(matches!(geocoder.resolve("  ?! "), Resolution::NotFound));
+        // "pyne" is a word of East Pyne Hall, "pyn" only shares two trigrams with it
+        assert_eq!(geocoder.search("pyne", MAX_CANDIDATES).len(), 1);
+        assert!(geocoder.search("pyn", MAX_CANDIDATES).is_empty());
+        assert!(matches!(geocoder.resolve("pyn"), Resolution::NotFound));
+    }
+}
@@ -0,0 +1,424 @@
+use crate::alt::LandmarkTables;
+use crate::ch::ContractionHierarchy;
+use crate::database::{self, RawNode};
+use crate::geocode::Geocoder;
+use crate::mode::TravelMode;
+use crate::snap::{self, EdgeSnap};
+use crate::spatial::SpatialIndex;
+use crate::traffic::TrafficModel;
+use crate::ways::{OsmWay, WayTags};
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use tokio::time::Instant;
+
+use lambda_runtime::tracing::info;
+
+// First radius searched for a nearby edge, doubled until one is found
+const SNAP_RADIUS_METERS: f64 = 50.0;
+
+// Row of nodes.csv (id,lon,lat)
+#[derive(Debug, Deserialize)]
+struct CsvNode {
+    id: i64,
+    lon: f64,
+    lat: f64,
+}
+
+// Row of edges.csv; the endpoints plus the per-mode access classifications
+#[derive(Debug, Deserialize)]
+struct CsvEdge {
+    // The way the edge was cut from
+    osm_id: i64,
+    source: i64,
+    target: i64,
+    length: f64,
+    foot: String,
+    car_forward: String,
+    car_backward: String,
+    bike_forward: String,
+    bike_backward: String,
+    wkt: String,
+}
+
+// Add an edge with its stored length to one mode's lists. Parallel ways between the
+// same two nodes keep the shorter one; returns true when this edge is the one kept.
+fn push_edge(adjacency_list: &mut Vec<i64>, lengths: &mut Vec<f64>, neighbor_id: i64, length: f64) -> bool {
+    match adjacency_list.iter().position(|id| *id == neighbor_id) {
+        Some(index) if length < lengths[index] => {
+            lengths[index] = length;
+            true
+        }
+        Some(_) => false,
+        None => {
+            adjacency_list.push(neighbor_id);
+            lengths.push(length);
+            true
+        }
+    }
+}
+
+// "LINESTRING(lon lat, lon lat, ...)" -> [[lon, lat], ...]
+fn parse_linestring(wkt: &str) -> Option<Vec<[f64; 2]>> {
+    let coordinates = wkt.trim().strip_prefix("LINESTRING")?.trim();
+    let coordinates = coordinates.strip_prefix('(')?.strip_suffix(')')?;
+
+    coordinates
+        .split(',')
+        .map(|pair| {
+            let mut values = pair.split_whitespace().map(|value| value.parse::<f64>().ok());
+            let lon = values.next()??;
+            let lat = values.next()??;
+            Some([lon, lat])
+        })
+        .collect()
+}
+
+// Fill in the car and bike in-neighbor lists from the out-neighbor lists
+fn link_reverse_adjacency(nodes: &mut HashMap<i64, RawNode>) {
+    let mut car_reverse: HashMap<i64, Vec<i64>> = HashMap::new();
+    let mut bike_reverse: HashMap<i64, Vec<i64>> = HashMap::new();
+    for node in nodes.values() {
+        for neighbor_id in node.car_adjacency_list.iter().flatten() {
+            car_reverse.entry(*neighbor_id).or_default().push(node.id);
+        }
+        for neighbor_id in node.bike_adjacency_list.iter().flatten() {
+            bike_reverse.entry(*neighbor_id).or_default().push(node.id);
+        }
+    }
+
+    for node in nodes.values_mut() {
+        if node.car_adjacency_list.is_some() {
+            node.car_reverse_adjacency_list = Some(car_reverse.remove(&node.id).unwrap_or_default());
+        }
+        if node.bike_adjacency_list.is_some() {
+            node.bike_reverse_adjacency_list = Some(bike_reverse.remove(&node.id).unwrap_or_default());
+        }
+    }
+}
+
+// edges.csv marks a direction that may not be used with "Forbidden"
+fn is_allowed(access: &str) -> bool {
+    access != "Forbidden"
+}
+
+// Shape and way of one edges.csv row, stored in source -> target order
+#[derive(Debug)]
+struct EdgeShape {
+    source: i64,
+    osm_id: i64,
+    geometry: Vec<[f64; 2]>,
+}
+
+/// Routing graph held in memory so a search never has to go back to the database.
+///
+/// Built once per Lambda container (cold start) and shared by every invocation.
+#[derive(Debug, Default)]
+pub struct Graph {
+    nodes: HashMap<i64, RawNode>,
+    // Rows of edges.csv behind the adjacency lists. Every directed edge of every mode
+    // points at the row its stored length came from.
+    shapes: Vec<EdgeShape>,
+    edge_rows: HashMap<(TravelMode, i64, i64), usize>,
+    // Preprocessed contraction hierarchies, attached after loading when CH_PATH is set
+    hierarchies: HashMap<TravelMode, ContractionHierarchy>,
+    // Landmark distance tables for ALT, attached when ALT_PATH is set
+    landmarks: HashMap<TravelMode, LandmarkTables>,
+    // Hourly congestion factors, attached when edge traffic volumes are available
+    traffic: Option<TrafficModel>,
+    // Name and highway class of the way behind every edge, in both directions
+    ways: HashMap<i64, WayTags>,
+    edge_ways: HashMap<(i64, i64), i64>,
+    // Grid over nodes and edges for snapping, built with the graph
+    index: SpatialIndex,
+    // Named places for waypoints given by name
+    geocoder: Option<Geocoder>,
+}
+
+impl Graph {
+    pub fn from_nodes(nodes: Vec<RawNode>) -> Graph {
+        let mut nodes = nodes.into_iter().map(|node| (node.id, node)).collect();
+        link_reverse_adjacency(&mut nodes);
+        let mut graph = Graph {
+            nodes,
+            ..Graph::default()
+        };
+        graph.build_index();
+        graph
+    }
+
+    // Load planet_osm_nodes + adjacent_nodes into memory with a single query.
+    // Every way node is kept, so edges are straight and carry no geometry.
+    pub async fn load(pool: &sqlx::PgPool) -> Result<Graph, io::Error> {
+        let load_start = Instant::now();
+        let columns = database::AdjacencyColumns::detect(pool).await?;
+        let nodes = database::get_all_nodes(pool, columns).await?;
+        let graph = Graph::from_nodes(nodes);
+
+        info!(
+            "Graph loaded with {} nodes and {} adjacency entries in {:?}",
+            graph.node_count(),
+            graph.edge_count(),
+            load_start.elapsed()
+        );
+
+        Ok(graph)
+    }
+
+    // Build the graph from the nodes.csv / edges.csv pair without touching Postgres
+    pub fn from_csv(nodes_path: &str, edges_path: &str) -> Result<Graph, io::Error> {
+        let load_start = Instant::now();
+
+        let mut nodes: HashMap<i64, RawNode> = HashMap::new();
+        let mut nodes_reader = csv::Reader::from_path(nodes_path).map_err(io::Error::other)?;
+        for row in nodes_reader.deserialize() {
+            let row: CsvNode = row.map_err(io::Error::other)?;
+            nodes.insert(
+                row.id,
+                RawNode {
+                    id: row.id,
+                    lon: row.lon,
+                    lat: row.lat,
+                    adjacency_list: Vec::new(),
+                    adjacency_lengths: Some(Vec::new()),
+                    car_adjacency_list: Some(Vec::new()),
+                    bike_adjacency_list: Some(Vec::new()),
+                    car_adjacency_lengths: Some(Vec::new()),
+                    bike_adjacency_lengths: Some(Vec::new()),
+                    car_reverse_adjacency_list: None,
+                    bike_reverse_adjacency_list: None,
+                },
+            );
+        }
+
+        let mut shapes: Vec<EdgeShape> = Vec::new();
+        let mut edge_rows: HashMap<(TravelMode, i64, i64), usize> = HashMap::new();
+        let mut edges_reader = csv::Reader::from_path(edges_path).map_err(io::Error::other)?;
+        for row in edges_reader.deserialize() {
+            let row: CsvEdge = row.map_err(io::Error::other)?;
+            if row.source == row.target {
+                continue; // Loops never shorten a path
+            }
+            if !nodes.contains_key(&row.source) || !nodes.contains_key(&row.target) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Edge {} -> {} references a node missing from {}", row.source, row.target, nodes_path),
+                ));
+            }
+
+            // Walkable edges go both ways like adjacent_nodes,
+            // car and bike lists only get the directions the edge may be driven or ridden
+            let directions = [
+                (row.source, row.target, &row.car_forward, &row.bike_forward),
+                (row.target, row.source, &row.car_backward, &row.bike_backward),
+            ];
+            let mut kept = Vec::new();
+            for (from, to, car_access, bike_access) in directions {
+                let node = nodes.get_mut(&from).unwrap();
+                let lists = [
+                    (TravelMode::Foot, is_allowed(&row.foot), &mut node.adjacency_list, &mut node.adjacency_lengths),
+                    (
+                        TravelMode::Car,
+                        is_allowed(car_access),
+                        node.car_adjacency_list.get_or_insert_with(Vec::new),
+                        &mut node.car_adjacency_lengths,
+                    ),
+                    (
+                        TravelMode::Bike,
+                        is_allowed(bike_access),
+                        node.bike_adjacency_list.get_or_insert_with(Vec::new),
+                        &mut node.bike_adjacency_lengths,
+                    ),
+                ];
+                for (mode, allowed, neighbors, lengths) in lists {
+                    if allowed && push_edge(neighbors, lengths.get_or_insert_with(Vec::new), to, row.length) {
+                        kept.push((mode, from, to));
+                    }
+                }
+            }
+
+            // Edges span several way nodes, so edge_way reads the way from the row kept
+            if !kept.is_empty() {
+                let geometry = parse_linestring(&row.wkt).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid WKT for edge {} -> {}", row.source, row.target))
+                })?;
+                for key in kept {
+                    edge_rows.insert(key, shapes.len());
+                }
+                shapes.push(EdgeShape {
+                    source: row.source,
+                    osm_id: row.osm_id,
+                    geometry,
+                });
+            }
+        }
+
+        link_reverse_adjacency(&mut nodes);
+        let mut graph = Graph {
+            nodes,
+            shapes,
+            edge_rows,
+            ..Graph::default()
+        };
+        graph.build_index();
+        info!(
+            "Graph loaded from {} and {} with {} nodes and {} adjacency entries in {:?}",
+            nodes_path,
+            edges_path,
+            graph.node_count(),
+            graph.edge_count(),
+            load_start.elapsed()
+        );
+
+        Ok(graph)
+    }
+
+    fn build_index(&mut self) {
+        let index_start = Instant::now();
+        // Parallel ways may differ per mode, the indexed box covers every shape kept
+        let shapes = |from_id, to_id| {
+            let points: Vec<[f64; 2]> = [TravelMode::Foot, TravelMode::Car, TravelMode::Bike]
+                .into_iter()
+                .flat_map(|mode| [self.edge_geometry(mode, from_id, to_id), self.edge_geometry(mode, to_id, from_id)])
+                .flatten()
+                .flatten()
+                .collect();
+            (!points.is_empty()).then_some(points)
+        };
+        self.index = SpatialIndex::build(self.nodes.values(), shapes);
+        info!("Spatial index over {} routable nodes built in {:?}", self.index.node_count(), index_start.elapsed());
+    }
+
+    pub fn get_node(&self, id: i64) -> Option<&RawNode> {
+        self.nodes.get(&id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &RawNode> {
+        self.nodes.values()
+    }
+
+    pub fn set_hierarchy(&mut self, hierarchy: ContractionHierarchy) {
+        self.hierarchies.insert(hierarchy.mode(), hierarchy);
+    }
+
+    pub fn hierarchy(&self, mode: TravelMode) -> Option<&ContractionHierarchy> {
+        self.hierarchies.get(&mode)
+    }
+
+    pub fn set_landmarks(&mut self, tables: LandmarkTables) {
+        self.landmarks.insert(tables.mode(), tables);
+    }
+
+    pub fn landmarks(&self, mode: TravelMode) -> Option<&LandmarkTables> {
+        self.landmarks.get(&mode)
+    }
+
+    pub fn set_traffic(&mut self, traffic: TrafficModel) {
+        self.traffic = Some(traffic);
+    }
+
+    pub fn traffic(&self) -> Option<&TrafficModel> {
+        self.traffic.as_ref()
+    }
+
+    pub fn set_geocoder(&mut self, geocoder: Geocoder) {
+        self.geocoder = Some(geocoder);
+    }
+
+    pub fn geocoder(&self) -> Option<&Geocoder> {
+        self.geocoder.as_ref()
+    }
+
+    // Attach way tags, every consecutive node pair of a way becomes one of its edges.
+    // CSV graphs already know the way of each edge and only need the tags.
+    pub fn set_ways(&mut self, ways: &[OsmWay]) {
+        for way in ways {
+            for pair in way.nodes.windows(2) {
+                self.edge_ways.insert((pair[0], pair[1]), way.id);
+                self.edge_ways.insert((pair[1], pair[0]), way.id);
+            }
+            self.ways.insert(way.id, WayTags::from(way));
+        }
+    }
+
+    pub fn way_count(&self) -> usize {
+        self.ways.len()
+    }
+
+    // Tags of the way the edge `mode` travels from `from_id` to `to_id` belongs to
+    pub fn edge_way(&self, mode: TravelMode, from_id: i64, to_id: i64) -> Option<&WayTags> {
+        let way_id = match self.edge_rows.get(&(mode, from_id, to_id)) {
+            Some(row) => self.shapes[*row].osm_id,
+            None => *self.edge_ways.get(&(from_id, to_id))?,
+        };
+        self.ways.get(&way_id)
+    }
+
+    // Shape of the edge `mode` travels from `from_id` to `to_id` with both endpoints,
+    // oriented in travel direction
+    pub fn edge_geometry(&self, mode: TravelMode, from_id: i64, to_id: i64) -> Option<Vec<[f64; 2]>> {
+        let shape = &self.shapes[*self.edge_rows.get(&(mode, from_id, to_id))?];
+        let mut geometry = shape.geometry.clone();
+        if shape.source != from_id {
+            geometry.reverse();
+        }
+        Some(geometry)
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.nodes.values().map(|node| node.adjacency_list.len()).sum()
+    }
+
+    // Closest node `mode` can leave from
+    pub fn nearest_node(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Option<&RawNode> {
+        self.nearest_nodes(latitude, longitude, 1, mode)
+            .into_iter()
+            .next()
+            .map(|(node, _)| node)
+    }
+
+    // The `k` closest nodes `mode` can leave from with their distances in meters, nearest first
+    pub fn nearest_nodes(&self, latitude: f64, longitude: f64, k: usize, mode: TravelMode) -> Vec<(&RawNode, f64)> {
+        self.index
+            .nearest(latitude, longitude, k, |id| !self.nodes[&id].adjacency(mode).is_empty())
+            .into_iter()
+            .map(|(id, distance)| (&self.nodes[&id], distance))
+            .collect()
+    }
+
+    // Every node `mode` can leave from within `radius` meters, nearest first
+    pub fn nodes_within(&self, latitude: f64, longitude: f64, radius: f64, mode: TravelMode) -> Vec<(&RawNode, f64)> {
+        self.index
+            .within(latitude, longitude, radius, |id| !self.nodes[&id].adjacency(mode).is_empty())
+            .into_iter()
+            .map(|(id, distance)| (&self.nodes[&id], distance))
+            .collect()
+    }
+
+    // Closest edge `mode` may travel along, widening the search until nothing farther
+    // out could be closer
+    pub fn nearest_edge(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Option<EdgeSnap> {
+        let mut radius = SNAP_RADIUS_METERS;
+        loop {
+            let (pairs, complete) = self.index.edges_near(latitude, longitude, radius);
+            let edges = pairs
+                .into_iter()
+                .flat_map(|(a, b)| [(a, b), (b, a)])
+                .filter_map(|(from_id, to_id)| {
+                    let from = self.nodes.get(&from_id)?;
+                    from.adjacency(mode).contains(&to_id).then_some((from, self.nodes.get(&to_id)?))
+                });
+            let geometry = |from_id, to_id| self.edge_geometry(mode, from_id, to_id);
+            match snap::nearest_edge(mode, edges, geometry, latitude, longitude) {
+                Some(edge) if edge.distance <= radius => return Some(edge),
+                nearest if complete => return nearest,
+                _ => radius *= 2.0,
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::database::RawNode;
+use crate::dijkstra::{edge_weight, explore};
+use crate::mode::TravelMode;
+use crate::snap::is_virtual;
+use crate::source::GraphSource;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use lambda_runtime::tracing::info;
+
+/// What an isochrone budget is measured in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetUnit {
+    #[default]
+    Meters,
+    Seconds,
+}
+
+/// A node reachable within the budget.
+#[derive(Clone, Debug, Serialize)]
+pub struct ReachableNode {
+    pub id: i64,
+    pub coordinates: [f64; 2],
+    /// Network distance from the origin in meters.
+    pub distance: f64,
+}
+
+/// Area reachable from one point within a budget.
+#[derive(Clone, Debug, Serialize)]
+pub struct Isochrone {
+    pub origin: [f64; 2],
+    pub nodes: Vec<ReachableNode>,
+    /// GeoJSON Polygon around the reachable nodes, null when fewer than three.
+    pub polygon: Value,
+    pub nodes_expanded: usize,
+    pub timed_out: bool,
+}
+
+fn cross(o: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+// Andrew's monotone chain, counter-clockwise and closed as GeoJSON rings are
+pub fn convex_hull(points: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut lower: Vec<[f64; 2]> = Vec::new();
+    for point in &points {
+        while lower.len() >= 2 && cross(lower[lower.len() - 2], lower[lower.len() - 1], *point) <= 0.0 {
+            lower.pop();
+        }
+        lower.push(*point);
+    }
+    let mut upper: Vec<[f64; 2]> = Vec::new();
+    for point in points.iter().rev() {
+        while upper.len() >= 2 && cross(upper[upper.len() - 2], upper[upper.len() - 1], *point) <= 0.0 {
+            upper.pop();
+        }
+        upper.push(*point);
+    }
+
+    // Each chain ends where the other starts; keeping upper's last point closes the ring
+    lower.pop();
+    lower.extend(upper);
+    lower
+}
+
+// Dijkstra from `origin` without a destination, stopping once the budget is spent
+pub async fn isochrone<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    origin: RawNode,
+    budget: f64,
+    unit: BudgetUnit,
+) -> Isochrone {
+    let max_distance = match unit {
+        BudgetUnit::Meters => budget,
+        BudgetUnit::Seconds => budget * mode.speed_mps(),
+    };
+    info!("Isochrone started for {:?} with a {:.0} m budget", mode, max_distance);
+
+    let exploration = explore(source, mode, &origin, &[], max_distance, |_| 0.0, |from, to, _| edge_weight(mode, from, to)).await;
+
+    let mut nodes: Vec<ReachableNode> = exploration
+        .distances
+        .iter()
+        .filter(|(id, distance)| **distance <= max_distance && !is_virtual(**id))
+        .filter_map(|(id, distance)| {
+            let node = exploration.nodes.get(*id)?;
+            Some(ReachableNode {
+                id: *id,
+                coordinates: [node.lon, node.lat],
+                distance: *distance,
+            })
+        })
+        .collect();
+    nodes.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+    let points: Vec<[f64; 2]> = nodes.iter().map(|node| node.coordinates).collect();
+    let hull = convex_hull(&points);
+    let polygon = if hull.len() >= 4 {
+        json!({ "type": "Polygon", "coordinates": [hull] })
+    } else {
+        Value::Null
+    };
+
+    Isochrone {
+        origin: [origin.lon, origin.lat],
+        nodes,
+        polygon,
+        nodes_expanded: exploration.expanded_count,
+        timed_out: exploration.timed_out,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Twice the signed area, positive for counter-clockwise rings
+    fn signed_area(ring: &[[f64; 2]]) -> f64 {
+        ring.windows(2).map(|pair| pair[0][0] * pair[1][1] - pair[1][0] * pair[0][1]).sum()
+    }
+
+    #[test]
+    fn hull_is_a_closed_counter_clockwise_ring() {
+        // A square with points inside it and on its edges
+        let points = [
+            [0.0, 0.0], [2.0, 2.0], [1.0, 1.0], [2.0, 0.0], [0.5, 1.5], [0.0, 2.0], [1.0, 0.0], [2.0, 1.0],
+        ];
+        let hull = convex_hull(&points);
+        assert_eq!(hull, [[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0], [0.0, 0.0]]);
+        assert_eq!(hull.first(), hull.last());
+        assert!((signed_area(&hull) - 8.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn duplicates_are_dropped() {
+        let points = [[0.0, 0.0], [1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [0.0, 1.0]];
+        assert_eq!(convex_hull(&points), [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [0.0, 0.0]]);
+        assert_eq!(convex_hull(&[[3.0, 4.0]; 5]), [[3.0, 4.0]]);
+    }
+
+    #[test]
+    fn collinear_points_collapse_to_their_ends() {
+        let points = [[1.0, 1.0], [0.0, 0.0], [3.0, 3.0], [2.0, 2.0]];
+        assert_eq!(convex_hull(&points), [[0.0, 0.0], [3.0, 3.0], [0.0, 0.0]]);
+    }
+
+    #[test]
+    fn fewer_than_three_points_come_back_sorted() {
+        assert!(convex_hull(&[]).is_empty());
+        assert_eq!(convex_hull(&[[1.0, 2.0]]), [[1.0, 2.0]]);
+        assert_eq!(convex_hull(&[[1.0, 2.0], [0.0, 5.0]]), [[0.0, 5.0], [1.0, 2.0]]);
+    }
+}
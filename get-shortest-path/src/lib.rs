@@ -0,0 +1,449 @@
+pub mod algorithm;
+pub mod alt;
+pub mod alternatives;
+pub mod bidirectional;
+pub mod ch;
+// The original queries predate these lints and are kept as written
+#[allow(clippy::io_other_error, clippy::tabs_in_doc_comments)]
+pub mod database;
+pub mod dijkstra;
+pub mod geocode;
+pub mod graph;
+pub mod isochrone;
+pub mod matching;
+pub mod matrix;
+pub mod mode;
+pub mod output;
+pub mod snap;
+pub mod source;
+pub mod spatial;
+pub mod streets;
+pub mod traffic;
+pub mod waypoints;
+pub mod ways;
+#[cfg(test)]
+mod test_support;
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::Instant;
+use lambda_runtime::tracing::{info, error};
+
+use crate::algorithm::Algorithm;
+use crate::geocode::{Candidate, Resolution};
+use crate::alternatives::AlternativeRoute;
+use crate::isochrone::{BudgetUnit, Isochrone};
+use crate::matrix::DistanceMatrix;
+use crate::dijkstra::SearchResult;
+use crate::mode::TravelMode;
+use crate::snap::{EdgeSnap, SnapOverlay, SnappedPoint};
+use crate::source::GraphSource;
+use crate::streets::{Street, StreetRun};
+
+/// Per-leg search statistics, one entry for each consecutive pair of points.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Leg {
+    pub distance: f64,
+    // Estimated travel time in seconds
+    pub duration: f64,
+    pub nodes_expanded: usize,
+    pub timed_out: bool,
+}
+
+/// A route through every requested point in order.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Route {
+    /// [lon, lat] points along the streets, through every node and edge shape of the path.
+    pub path: Vec<[f64; 2]>,
+    pub distance: f64,
+    pub duration: f64,
+    pub legs: Vec<Leg>,
+    /// Where each requested point joined the graph, in request order.
+    pub snapped: Vec<SnappedPoint>,
+    /// Streets the path follows, as runs of path vertices.
+    pub streets: Vec<StreetRun>,
+}
+
+impl Route {
+    pub fn timed_out(&self) -> bool {
+        self.legs.iter().any(|leg| leg.timed_out)
+    }
+
+    // Append the next leg, dropping its first point which ends the previous leg
+    fn push_leg<S: GraphSource>(&mut self, source: &S, mode: TravelMode, segment: SearchResult) {
+        let offset = self.path.len().saturating_sub(1);
+        for run in streets::street_runs(source, mode, &segment.node_ids, &segment.path) {
+            let run = StreetRun {
+                from_index: run.from_index + offset,
+                to_index: run.to_index + offset,
+                ..run
+            };
+            streets::push_run(&mut self.streets, run);
+        }
+
+        if self.legs.is_empty() {
+            self.path.extend(segment.path);
+        } else if segment.path.len() > 1 {
+            self.path.extend_from_slice(&segment.path[1..]);
+        }
+        self.distance += segment.distance;
+        self.duration += segment.duration;
+        self.legs.push(Leg {
+            distance: segment.distance,
+            duration: segment.duration,
+            nodes_expanded: segment.nodes_expanded,
+            timed_out: segment.timed_out,
+        });
+    }
+}
+
+/// A requested point, either `[lat, lon]` or the name of a place.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Waypoint {
+    Coordinates(f64, f64),
+    Name(String),
+}
+
+impl Waypoint {
+    pub fn is_name(&self) -> bool {
+        matches!(self, Waypoint::Name(_))
+    }
+}
+
+/// Where a requested point is, with the place name it was given by if any.
+#[derive(Clone, Debug, Serialize)]
+pub struct ResolvedWaypoint {
+    pub name: Option<String>,
+    /// [lon, lat]
+    pub location: [f64; 2],
+}
+
+impl ResolvedWaypoint {
+    pub fn point(&self) -> (f64, f64) {
+        (self.location[1], self.location[0])
+    }
+}
+
+/// A waypoint name that did not resolve to a single place, with the places it may mean.
+#[derive(Clone, Debug, Serialize)]
+pub struct Unresolved {
+    pub index: usize,
+    pub query: String,
+    pub candidates: Vec<Candidate>,
+}
+
+// Look up every named waypoint. Either all of them resolve, or the caller gets each one
+// that did not, so a client can ask about all of them at once.
+pub fn resolve_waypoints<S: GraphSource>(
+    source: &S,
+    waypoints: &[Waypoint],
+) -> Result<Vec<ResolvedWaypoint>, Vec<Unresolved>> {
+    let mut resolved = Vec::with_capacity(waypoints.len());
+    let mut unresolved = Vec::new();
+    for (index, waypoint) in waypoints.iter().enumerate() {
+        let query = match waypoint {
+            Waypoint::Coordinates(lat, lon) => {
+                resolved.push(ResolvedWaypoint { name: None, location: [*lon, *lat] });
+                continue;
+            }
+            Waypoint::Name(query) => query,
+        };
+        let resolution = match source.geocoder() {
+            Some(geocoder) => geocoder.resolve(query),
+            None => Resolution::NotFound,
+        };
+        match resolution {
+            Resolution::Found(place) => {
+                info!("Waypoint {} '{}' resolved to {} ({})", index, query, place.name, place.osm_id);
+                resolved.push(ResolvedWaypoint { name: Some(place.name), location: [place.lon, place.lat] });
+            }
+            Resolution::Ambiguous(candidates) => {
+                unresolved.push(Unresolved { index, query: query.clone(), candidates });
+            }
+            Resolution::NotFound => {
+                unresolved.push(Unresolved { index, query: query.clone(), candidates: Vec::new() });
+            }
+        }
+    }
+    if unresolved.is_empty() {
+        Ok(resolved)
+    } else {
+        Err(unresolved)
+    }
+}
+
+// Snap a coordinate onto the nearest edge, `label` names the point in logs and errors
+async fn snap<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    latitude: f64,
+    longitude: f64,
+    label: &str,
+) -> Result<EdgeSnap, io::Error> {
+    let start_time = Instant::now();
+    match source.nearest_edge(latitude, longitude, mode).await {
+        Ok(Some(edge)) => {
+            info!("{} snapped {:.1} m onto the graph in {:?}", label, edge.distance, start_time.elapsed());
+            Ok(edge)
+        }
+        Ok(None) => {
+            error!("{} node not found", label);
+            Err(io::Error::new(io::ErrorKind::NotFound, format!("{} node not found", label)))
+        }
+        Err(e) => {
+            error!("Error fetching {} node: {:?}", label.to_lowercase(), e);
+            Err(e)
+        }
+    }
+}
+
+// Snap the points of a route in order, naming them by position
+async fn snap_route<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    points: &[(f64, f64)],
+) -> Result<Vec<EdgeSnap>, io::Error> {
+    let mut snaps = Vec::with_capacity(points.len());
+    for (i, (lat, lon)) in points.iter().enumerate() {
+        let label = match i {
+            0 => "Source".to_string(),
+            i if i == points.len() - 1 => "Destination".to_string(),
+            i => format!("Waypoint {}", i),
+        };
+        snaps.push(snap(source, mode, *lat, *lon, &label).await?);
+    }
+    Ok(snaps)
+}
+
+pub async fn get_shortest_path<S: GraphSource>(
+    source: &S,
+    algorithm: Algorithm,
+    mode: TravelMode,
+    start_lat: f64,
+    start_lon: f64,
+    end_lat: f64,
+    end_lon: f64,
+) -> Result<SearchResult, io::Error> {
+    let snaps = snap_route(source, mode, &[(start_lat, start_lon), (end_lat, end_lon)]).await?;
+    let overlay = SnapOverlay::new(source, &snaps);
+
+    let path_start_time = Instant::now();
+    let result = dijkstra::shortest_path(&overlay, algorithm, mode, overlay.endpoint(0), overlay.endpoint(1)).await;
+    info!(
+        "Path calculation with {:?} completed in {:?}, {} nodes expanded",
+        algorithm,
+        path_start_time.elapsed(),
+        result.nodes_expanded
+    );
+
+    Ok(result)
+}
+
+pub async fn get_shortest_path_multiple<S: GraphSource>(
+    source: &S,
+    algorithm: Algorithm,
+    mode: TravelMode,
+    points: Vec<(f64, f64)>,
+) -> Result<Route, io::Error> {
+    if points.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "At least two points are required to calculate a path.",
+        ));
+    }
+
+    // Every point is snapped once, legs share the overlay of virtual nodes
+    let snaps = snap_route(source, mode, &points).await?;
+    let overlay = SnapOverlay::new(source, &snaps);
+    let mut route = Route {
+        snapped: snaps.iter().map(EdgeSnap::snapped_point).collect(),
+        ..Route::default()
+    };
+
+    for i in 0..points.len() - 1 {
+        let segment_start_time = Instant::now();
+        let segment =
+            dijkstra::shortest_path(&overlay, algorithm, mode, overlay.endpoint(i), overlay.endpoint(i + 1)).await;
+        info!(
+            "Segment {}-{} completed in {:?}",
+            i,
+            i + 1,
+            segment_start_time.elapsed()
+        );
+
+        route.push_leg(&overlay, mode, segment);
+    }
+
+    Ok(route)
+}
+
+// Route through every point leaving the first one `departure` seconds after midnight.
+// Each leg starts when the previous one is expected to arrive.
+pub async fn get_time_dependent_route<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    points: Vec<(f64, f64)>,
+    departure: f64,
+) -> Result<Route, io::Error> {
+    if points.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "At least two points are required to calculate a path.",
+        ));
+    }
+
+    let snaps = snap_route(source, mode, &points).await?;
+    let overlay = SnapOverlay::new(source, &snaps);
+    let mut route = Route {
+        snapped: snaps.iter().map(EdgeSnap::snapped_point).collect(),
+        ..Route::default()
+    };
+
+    for i in 0..points.len() - 1 {
+        let segment_start_time = Instant::now();
+        let segment = traffic::time_dependent(
+            &overlay,
+            mode,
+            overlay.endpoint(i),
+            overlay.endpoint(i + 1),
+            departure + route.duration,
+        )
+        .await;
+        info!(
+            "Segment {}-{} completed in {:?}, {:.0}s travel time",
+            i,
+            i + 1,
+            segment_start_time.elapsed(),
+            segment.duration
+        );
+
+        route.push_leg(&overlay, mode, segment);
+    }
+
+    Ok(route)
+}
+
+// Up to `k` meaningfully different routes between two points, best route first
+pub async fn get_alternative_routes<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    start: (f64, f64),
+    end: (f64, f64),
+    k: usize,
+) -> Result<Vec<AlternativeRoute>, io::Error> {
+    let snaps = snap_route(source, mode, &[start, end]).await?;
+    let overlay = SnapOverlay::new(source, &snaps);
+
+    let search_start_time = Instant::now();
+    let routes =
+        alternatives::alternative_routes(&overlay, mode, overlay.endpoint(0), overlay.endpoint(1), k).await;
+    info!("{} alternative routes found in {:?}", routes.len(), search_start_time.elapsed());
+
+    Ok(routes)
+}
+
+// Reverse geocoding: the street under a coordinate, through the edge it snaps onto
+pub async fn get_street<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    (lat, lon): (f64, f64),
+) -> Result<Street, io::Error> {
+    let snapped = snap(source, mode, lat, lon, "Point").await?;
+    let way = source.edge_way(mode, snapped.from.id, snapped.to.id).cloned();
+    match &way {
+        Some(way) => info!("Point is on way {} ({:?})", way.id, way.name),
+        None => info!("No way tags for edge {}-{}", snapped.from.id, snapped.to.id),
+    }
+
+    Ok(Street { way, snapped: snapped.snapped_point() })
+}
+
+// Everything reachable from one point within `budget` meters or seconds
+pub async fn get_isochrone<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    (lat, lon): (f64, f64),
+    budget: f64,
+    unit: BudgetUnit,
+) -> Result<Isochrone, io::Error> {
+    let origin = snap(source, mode, lat, lon, "Origin").await?;
+    let overlay = SnapOverlay::new(source, std::slice::from_ref(&origin));
+
+    let search_start_time = Instant::now();
+    let isochrone = isochrone::isochrone(&overlay, mode, overlay.endpoint(0), budget, unit).await;
+    info!(
+        "Isochrone with {} reachable nodes computed in {:?}",
+        isochrone.nodes.len(),
+        search_start_time.elapsed()
+    );
+
+    Ok(isochrone)
+}
+
+// Snap every origin and destination once, then run one search per origin.
+// All of them share one overlay so any origin can reach any snapped destination.
+pub async fn get_distance_matrix<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    origins: &[(f64, f64)],
+    destinations: &[(f64, f64)],
+) -> Result<DistanceMatrix, io::Error> {
+    let snap_start_time = Instant::now();
+    let mut snaps = Vec::with_capacity(origins.len() + destinations.len());
+    let mut snap_indices = Vec::with_capacity(origins.len() + destinations.len());
+    for (lat, lon) in origins.iter().chain(destinations) {
+        match source.nearest_edge(*lat, *lon, mode).await? {
+            Some(edge) => {
+                snap_indices.push(Some(snaps.len()));
+                snaps.push(edge);
+            }
+            None => snap_indices.push(None),
+        }
+    }
+    info!(
+        "Snapped {} points in {:?}",
+        origins.len() + destinations.len(),
+        snap_start_time.elapsed()
+    );
+
+    let overlay = SnapOverlay::new(source, &snaps);
+    let nodes: Vec<_> = snap_indices
+        .iter()
+        .map(|index| index.map(|index| overlay.endpoint(index)))
+        .collect();
+    let (origin_nodes, destination_nodes) = nodes.split_at(origins.len());
+
+    let search_start_time = Instant::now();
+    let matrix = matrix::distance_matrix(&overlay, mode, origin_nodes, destination_nodes).await;
+    info!("Distance matrix computed in {:?}", search_start_time.elapsed());
+
+    Ok(matrix)
+}
+
+// Reorder the stops between the first point (and with `keep_last` the last one) to
+// minimize total distance, then route through them; returns the visiting order chosen
+pub async fn get_optimized_route<S: GraphSource>(
+    source: &S,
+    algorithm: Algorithm,
+    mode: TravelMode,
+    points: Vec<(f64, f64)>,
+    keep_last: bool,
+) -> Result<(Vec<usize>, Route), io::Error> {
+    if points.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "At least two points are required to calculate a path.",
+        ));
+    }
+
+    let order_start_time = Instant::now();
+    let matrix = get_distance_matrix(source, mode, &points, &points).await?;
+    let order = waypoints::optimize_order(&matrix.distances, keep_last);
+    info!("Visiting order {:?} chosen in {:?}", order, order_start_time.elapsed());
+
+    let ordered_points = order.iter().map(|index| points[*index]).collect();
+    let route = get_shortest_path_multiple(source, algorithm, mode, ordered_points).await?;
+
+    Ok((order, route))
+}
@@ -1,23 +1,196 @@
-pub mod database;
-pub mod dijkstra;
-
-use lambda_http::{run, service_fn, Body, Error, Request, Response};
+use lambda_http::{run, service_fn, Body, Error, Request, RequestExt, Response};
 use serde_json::{json, Value};
-use std::io;
 use dotenv::dotenv;
 use std::env;
-use reqwest::Client;
+use std::io;
 use std::time::{Duration,Instant};
-use chrono::{Utc, DateTime};
-use lambda_runtime::tracing::{info, debug, error};
+use chrono::Utc;
+use lambda_runtime::tracing::info;
+
+use get_shortest_path::algorithm::Algorithm;
+use get_shortest_path::isochrone::BudgetUnit;
+use get_shortest_path::{
+    get_alternative_routes, get_distance_matrix, get_isochrone, get_optimized_route, get_shortest_path_multiple,
+    get_street, get_time_dependent_route, resolve_waypoints, Unresolved, Waypoint,
+};
+use get_shortest_path::mode::TravelMode;
+use get_shortest_path::output::{self, OutputFormat};
+use get_shortest_path::source::{self, Backend, GraphSource};
+use get_shortest_path::traffic;
+
+// Upper bound on the `alternatives` field, every route costs a full search
+const MAX_ALTERNATIVES: usize = 5;
+
+// Default for MATRIX_MAX_CELLS, each origin costs one search
+const DEFAULT_MATRIX_MAX_CELLS: usize = 400;
+
+// JSON error body for requests the router rejects
+fn error_response(status: u16, message: &str) -> Result<Response<Body>, Error> {
+    let resp = Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(json!({ "error": message }).to_string().into())
+        .map_err(Box::new)?;
+    Ok(resp)
+}
+
+// Points with nothing routable nearby are the caller's problem, anything else is ours
+fn routing_error(e: io::Error) -> Result<Response<Body>, Error> {
+    match e.kind() {
+        io::ErrorKind::NotFound => error_response(404, &e.to_string()),
+        io::ErrorKind::InvalidInput => error_response(400, &e.to_string()),
+        _ => Err(Box::new(e)),
+    }
+}
+
+// Waypoint names that did not resolve: 404 when nothing resembles any of them, otherwise
+// 422 with the candidate places so the client can pick one and retry with coordinates
+fn unresolved_response(unresolved: &[Unresolved]) -> Result<Response<Body>, Error> {
+    if unresolved.iter().all(|waypoint| waypoint.candidates.is_empty()) {
+        let names: Vec<String> = unresolved.iter().map(|waypoint| format!("'{}'", waypoint.query)).collect();
+        return error_response(404, &format!("No place named like {}", names.join(", ")));
+    }
+    let resp = Response::builder()
+        .status(422)
+        .header("content-type", "application/json")
+        .body(json!({ "error": "Ambiguous waypoint names", "unresolved": unresolved }).to_string().into())
+        .map_err(Box::new)?;
+    Ok(resp)
+}
+
+fn json_response(resp_json: &Value) -> Result<Response<Body>, Error> {
+    let resp = Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(resp_json).unwrap().into())
+        .map_err(Box::new)?;
+    Ok(resp)
+}
+
+// Isochrone request: {"type": "isochrone", "point": [lat, lon], "budget": 600, "unit": "meters" | "seconds"}
+async fn isochrone_handler(body_json: &Value, mode: TravelMode, source: &Backend) -> Result<Response<Body>, Error> {
+    let start_time = Instant::now();
+
+    let point: (f64, f64) = match serde_json::from_value(body_json["point"].clone()) {
+        Ok(point) => point,
+        Err(e) => return error_response(400, &format!("Invalid point: {}", e)),
+    };
+    let budget = match body_json.get("budget").and_then(Value::as_f64) {
+        Some(budget) if budget > 0.0 => budget,
+        _ => return error_response(400, "budget must be a positive number"),
+    };
+    let unit = match body_json.get("unit") {
+        None | Some(Value::Null) => BudgetUnit::default(),
+        Some(value) => match serde_json::from_value::<BudgetUnit>(value.clone()) {
+            Ok(unit) => unit,
+            Err(e) => return error_response(400, &format!("Invalid unit: {}", e)),
+        },
+    };
+
+    let isochrone = match get_isochrone(source, mode, point, budget, unit).await {
+        Ok(isochrone) => isochrone,
+        Err(e) => return routing_error(e),
+    };
+    info!("Isochrone request completed in {:?}", start_time.elapsed());
+
+    json_response(&json!({
+        "type": "isochrone",
+        "mode": mode,
+        "budget": budget,
+        "unit": unit,
+        "origin": isochrone.origin,
+        "nodes": isochrone.nodes,
+        "polygon": isochrone.polygon,
+        "nodes_expanded": isochrone.nodes_expanded,
+        "timeout": isochrone.timed_out,
+    }))
+}
+
+// Reverse geocode request: {"type": "reverse", "point": [lat, lon]}
+// Answers with the name and highway class of the street the point snaps onto.
+async fn reverse_handler(body_json: &Value, mode: TravelMode, source: &Backend) -> Result<Response<Body>, Error> {
+    let start_time = Instant::now();
+
+    let point: (f64, f64) = match serde_json::from_value(body_json["point"].clone()) {
+        Ok(point) => point,
+        Err(e) => return error_response(400, &format!("Invalid point: {}", e)),
+    };
+
+    let street = match get_street(source, mode, point).await {
+        Ok(street) => street,
+        Err(e) => return routing_error(e),
+    };
+    info!("Reverse geocode request completed in {:?}", start_time.elapsed());
+
+    let way = street.way.as_ref();
+    json_response(&json!({
+        "type": "reverse",
+        "mode": mode,
+        "name": way.and_then(|way| way.name.as_ref()),
+        "highway": way.and_then(|way| way.highway.as_ref()),
+        "way_id": way.map(|way| way.id),
+        "snapped": street.snapped,
+    }))
+}
+
+fn matrix_max_cells() -> usize {
+    env::var("MATRIX_MAX_CELLS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MATRIX_MAX_CELLS)
+}
+
+// Matrix request: {"type": "matrix", "origins": [[lat, lon], ...], "destinations": [[lat, lon], ...]}
+// Unreachable pairs come back as null. MATRIX_MAX_CELLS caps origins x destinations.
+async fn matrix_handler(body_json: &Value, mode: TravelMode, source: &Backend) -> Result<Response<Body>, Error> {
+    let start_time = Instant::now();
+
+    let origins: Vec<(f64, f64)> = match serde_json::from_value(body_json["origins"].clone()) {
+        Ok(origins) => origins,
+        Err(e) => return error_response(400, &format!("Invalid origins: {}", e)),
+    };
+    let destinations: Vec<(f64, f64)> = match serde_json::from_value(body_json["destinations"].clone()) {
+        Ok(destinations) => destinations,
+        Err(e) => return error_response(400, &format!("Invalid destinations: {}", e)),
+    };
+    if origins.is_empty() || destinations.is_empty() {
+        return error_response(400, "origins and destinations must not be empty");
+    }
+
+    let max_cells = matrix_max_cells();
+    if origins.len() * destinations.len() > max_cells {
+        return error_response(
+            400,
+            &format!(
+                "Matrix of {} x {} exceeds the limit of {} cells",
+                origins.len(),
+                destinations.len(),
+                max_cells
+            ),
+        );
+    }
 
-// Load configuration from environment
-pub fn load_config() -> String {
-    env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+    let matrix = match get_distance_matrix(source, mode, &origins, &destinations).await {
+        Ok(matrix) => matrix,
+        Err(e) => return routing_error(e),
+    };
+    info!("Matrix request completed in {:?}", start_time.elapsed());
+
+    json_response(&json!({
+        "type": "matrix",
+        "mode": mode,
+        "distances": matrix.distances,
+        "nodes_expanded": matrix.nodes_expanded,
+        "timeout": matrix.timed_out,
+    }))
 }
 
 // The function handler for Lambda
-async fn function_handler(event: Request, check_client: reqwest::Client) -> Result<Response<Body>, Error> {
+async fn function_handler(
+    event: Request,
+    _check_client: reqwest::Client,
+    source: &Backend,
+) -> Result<Response<Body>, Error> {
     fn log_event(event_name: &str, start: Instant) {
         info!("{} completed in {:?}", event_name, start.elapsed());
     }
@@ -32,36 +205,196 @@ async fn function_handler(event: Request, check_client: reqwest::Client) -> Resu
     let body = event.body();
     let body_vec: Vec<u8> = body[..].into();
     let body_json: Value = serde_json::from_slice(&body_vec)?;
-    let points: Vec<(f64, f64)> = serde_json::from_value(body_json["points"].clone())?;
-    log_event("Body parsing", body_parse_start);
 
-    // Load configuration
-    let config_load_start = Instant::now();
-    let config = load_config();
-    log_event("Config loading", config_load_start);
+    // Travel mode defaults to foot when the request does not name one
+    let mode = match body_json.get("mode") {
+        None | Some(Value::Null) => TravelMode::default(),
+        Some(value) => match serde_json::from_value::<TravelMode>(value.clone()) {
+            Ok(mode) => mode,
+            Err(e) => return error_response(400, &format!("Invalid mode: {}", e)),
+        },
+    };
+
+    // Routes come back as JSON unless the body or the query string asks for output=geojson,
+    // gpx or kml
+    let output_value = match body_json.get("output") {
+        None | Some(Value::Null) => event.query_string_parameters().first("output").map(|value| json!(value)),
+        Some(value) => Some(value.clone()),
+    };
+    let output = match output_value {
+        None => OutputFormat::default(),
+        Some(value) => match serde_json::from_value::<OutputFormat>(value) {
+            Ok(output) => output,
+            Err(e) => return error_response(400, &format!("Invalid output: {}", e)),
+        },
+    };
+
+    // Requests are routes unless they name another type
+    match body_json.get("type").and_then(Value::as_str) {
+        None | Some("route") => {}
+        Some(_) if output != OutputFormat::Json => {
+            return error_response(400, "Only route requests support an output format");
+        }
+        Some("isochrone") => return isochrone_handler(&body_json, mode, source).await,
+        Some("matrix") => return matrix_handler(&body_json, mode, source).await,
+        Some("reverse") => return reverse_handler(&body_json, mode, source).await,
+        Some(other) => return error_response(400, &format!("Unknown request type '{}'", other)),
+    }
+
+    // Points are [lat, lon] pairs or place names
+    let requested: Vec<Waypoint> = match serde_json::from_value(body_json["points"].clone()) {
+        Ok(requested) => requested,
+        Err(e) => return error_response(400, &format!("Invalid points: {}", e)),
+    };
+    if requested.iter().any(Waypoint::is_name) && source.geocoder().is_none() {
+        return error_response(400, "No place names loaded, points need coordinates");
+    }
+    let waypoints = match resolve_waypoints(source, &requested) {
+        Ok(waypoints) => waypoints,
+        Err(unresolved) => return unresolved_response(&unresolved),
+    };
+    let points: Vec<(f64, f64)> = waypoints.iter().map(|waypoint| waypoint.point()).collect();
+
+    // Plain Dijkstra unless the request asks for astar, bidirectional, ch or alt
+    let algorithm = match body_json.get("algorithm") {
+        None | Some(Value::Null) => Algorithm::default(),
+        Some(value) => match serde_json::from_value::<Algorithm>(value.clone()) {
+            Ok(algorithm) => algorithm,
+            Err(e) => return error_response(400, &format!("Invalid algorithm: {}", e)),
+        },
+    };
+    if algorithm == Algorithm::ContractionHierarchy && source.contraction_hierarchy(mode).is_none() {
+        return error_response(400, &format!("No contraction hierarchy loaded for mode {:?}", mode));
+    }
+    if algorithm == Algorithm::Alt && source.landmark_tables(mode).is_none() {
+        return error_response(400, &format!("No landmark tables loaded for mode {:?}", mode));
+    }
+
+    // Optional number of alternative routes, only for a single start/end pair
+    let alternatives = match body_json.get("alternatives") {
+        None | Some(Value::Null) => None,
+        Some(value) => match value.as_u64() {
+            Some(k) if (1..=MAX_ALTERNATIVES as u64).contains(&k) && points.len() == 2 => Some(k as usize),
+            Some(k) if (1..=MAX_ALTERNATIVES as u64).contains(&k) => {
+                return error_response(400, "Alternatives need exactly two points");
+            }
+            _ => {
+                return error_response(400, &format!("alternatives must be between 1 and {}", MAX_ALTERNATIVES));
+            }
+        },
+    };
 
-    // Create database pool
-    let pool_create_start = Instant::now();
-    let pool = database::create_pool(&config).await?;
-    log_event("Database pool creation", pool_create_start);
+    // optimize_order reorders the stops between the first point and, with keep_last, the last one
+    let optimize_order = body_json.get("optimize_order").and_then(Value::as_bool).unwrap_or(false);
+    let keep_last = body_json.get("keep_last").and_then(Value::as_bool).unwrap_or(false);
+    if optimize_order && points.len() * points.len() > matrix_max_cells() {
+        return error_response(400, &format!("Too many points to optimize the order of: {}", points.len()));
+    }
+
+    // departure_time switches to time-dependent Dijkstra with hour-of-day traffic
+    let departure_time = body_json.get("departure_time").and_then(Value::as_str);
+    let departure = match departure_time {
+        None => None,
+        Some(value) => match traffic::parse_departure_time(value) {
+            Some(seconds) => Some(seconds),
+            None => return error_response(400, &format!("Invalid departure_time '{}'", value)),
+        },
+    };
+    if departure.is_some() && algorithm != Algorithm::Dijkstra {
+        return error_response(400, "departure_time is only supported with the dijkstra algorithm");
+    }
+    if departure.is_some() && optimize_order {
+        return error_response(400, "departure_time cannot be combined with optimize_order");
+    }
+    log_event("Body parsing", body_parse_start);
 
     // Get the shortest path
     let path_calc_start = Instant::now();
-    let result = get_shortest_path_multiple(&pool, points).await;
+    let result = if let Some(departure) = departure {
+        get_time_dependent_route(source, mode, points.clone(), departure)
+            .await
+            .map(|route| (None, route))
+    } else if optimize_order {
+        get_optimized_route(source, algorithm, mode, points.clone(), keep_last)
+            .await
+            .map(|(order, route)| (Some(order), route))
+    } else {
+        get_shortest_path_multiple(source, algorithm, mode, points.clone())
+            .await
+            .map(|route| (None, route))
+    };
     log_event("Path calculation", path_calc_start);
 
-    // Map the result to a Vec<[f64; 2]>
-    let path = result
-        .map(|p| p.into_iter().map(|x| x as [f64; 2]).collect::<Vec<[f64; 2]>>())
-        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+    let (order, route) = match result {
+        Ok(result) => result,
+        Err(e) => return routing_error(e),
+    };
+
+    let alternative_routes = match alternatives {
+        Some(k) => {
+            let alternatives_start = Instant::now();
+            let routes = match get_alternative_routes(source, mode, points[0], points[1], k).await {
+                Ok(routes) => routes,
+                Err(e) => return routing_error(e),
+            };
+            log_event("Alternative routes", alternatives_start);
+            Some(routes)
+        }
+        None => None,
+    };
 
     // Build the response JSON
     let response_build_start = Instant::now();
-    let resp_json = json!({ "path": path, "timeout": start_time.elapsed() > timeout_threshold});
+    let nodes_expanded: Vec<usize> = route.legs.iter().map(|leg| leg.nodes_expanded).collect();
+    let snap_distances: Vec<f64> = route.snapped.iter().map(|snapped| snapped.distance).collect();
+    let mut resp_json = json!({
+        "path": route.path,
+        "mode": mode,
+        "algorithm": algorithm,
+        "distance": route.distance,
+        "duration": route.duration,
+        "nodes_expanded": nodes_expanded,
+        "snap_distances": snap_distances,
+        "snapped": route.snapped,
+        "streets": route.streets,
+        "timeout": route.timed_out() || start_time.elapsed() > timeout_threshold,
+    });
+    if requested.iter().any(Waypoint::is_name) {
+        resp_json["waypoints"] = json!(waypoints);
+    }
+    if let Some(departure_time) = departure_time {
+        resp_json["departure_time"] = json!(departure_time);
+    }
+    if let Some(routes) = &alternative_routes {
+        resp_json["alternatives"] = json!(routes);
+    }
+    if let Some(order) = &order {
+        resp_json["order"] = json!(order);
+    }
+    let body = match output {
+        OutputFormat::Json => serde_json::to_string(&resp_json).unwrap(),
+        OutputFormat::GeoJson => {
+            // Whatever the features do not already carry becomes a property of the route
+            let Value::Object(mut properties) = resp_json else { unreachable!() };
+            for key in ["path", "distance", "duration", "snapped", "snap_distances", "waypoints", "alternatives"] {
+                properties.remove(key);
+            }
+            let geojson = output::route_geojson(
+                &route,
+                properties,
+                &waypoints,
+                order.as_deref(),
+                alternative_routes.as_deref(),
+            );
+            serde_json::to_string(&geojson).unwrap()
+        }
+        OutputFormat::Gpx => output::route_gpx(&route, &waypoints, order.as_deref()),
+        OutputFormat::Kml => output::route_kml(&route, &waypoints, order.as_deref()),
+    };
     let resp = Response::builder()
         .status(200)
-        .header("content-type", "application/json")
-        .body(serde_json::to_string(&resp_json).unwrap().into())
+        .header("content-type", output.content_type())
+        .body(body.into())
         .map_err(Box::new)?;
     log_event("Response building", response_build_start);
 
@@ -86,90 +419,11 @@ async fn main() -> Result<(), lambda_http::Error> {
 
     let check_client = reqwest::Client::new();
 
+    // The graph backend is built once per container and reused across invocations
+    let source = source::load_source().await?;
+
     run(service_fn(|event: Request| async {
-        function_handler(event, check_client.clone()).await
+        function_handler(event, check_client.clone(), &source).await
     }))
     .await
 }
-
-async fn get_shortest_path(
-    pool: &sqlx::PgPool,
-    start_lat: f64,
-    start_lon: f64,
-    end_lat: f64,
-    end_lon: f64,
-) -> Result<Vec<[f64; 2]>, io::Error> {
-    let src_start_time = Instant::now();
-    let src_node = match database::get_node_by_lat_lon(pool, start_lat, start_lon).await {
-        Ok(Some(node)) => {
-            info!("Source node fetched in {:?}", src_start_time.elapsed());
-            node
-        }
-        Ok(None) => {
-            error!("Source node not found");
-            return Err(io::Error::new(io::ErrorKind::NotFound, "Source node not found"));
-        }
-        Err(e) => {
-            error!("Error fetching source node: {:?}", e);
-            return Err(e);
-        }
-    };
-
-    let dest_start_time = Instant::now();
-    let dest_node = match database::get_node_by_lat_lon(pool, end_lat, end_lon).await {
-        Ok(Some(node)) => {
-            info!("Destination node fetched in {:?}", dest_start_time.elapsed());
-            node
-        }
-        Ok(None) => {
-            error!("Destination node not found");
-            return Err(io::Error::new(io::ErrorKind::NotFound, "Destination node not found"));
-        }
-        Err(e) => {
-            error!("Error fetching destination node: {:?}", e);
-            return Err(e);
-        }
-    };
-
-    let path_start_time = Instant::now();
-    let path = dijkstra::dijkstra(pool, src_node, dest_node).await;
-    info!("Path calculation completed in {:?}", path_start_time.elapsed());
-
-    Ok(path)
-}
-
-async fn get_shortest_path_multiple(
-    pool: &sqlx::PgPool,
-    points: Vec<(f64, f64)>,
-) -> Result<Vec<[f64; 2]>, io::Error> {
-    if points.len() < 2 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "At least two points are required to calculate a path.",
-        ));
-    }
-
-    let mut full_path: Vec<[f64; 2]> = Vec::new();
-
-    for i in 0..points.len() - 1 {
-        let (start_lat, start_lon) = points[i];
-        let (end_lat, end_lon) = points[i + 1];
-
-        let segment_start_time = Instant::now();
-        let segment_path = get_shortest_path(pool, start_lat, start_lon, end_lat, end_lon).await?;
-        info!(
-            "Segment {}-{} completed in {:?}",
-            i,
-            i + 1,
-            segment_start_time.elapsed()
-        );
-
-        if i == 0 {
-            full_path.extend(segment_path);
-        } else if segment_path.len() > 1 {
-            full_path.extend(segment_path[1..].to_vec());
-        }
-    }
-
-    Ok(full_path)
-}
@@ -0,0 +1,491 @@
+// Map-matching of traffic count locations onto the routing graph. A count sits at a
+// single point with a street name and a travel direction, the graph only knows OSM
+// node ids; this finds the directed car edges each count describes.
+
+use crate::graph::Graph;
+use crate::mode::TravelMode;
+use crate::snap::{project_onto_segment, to_local};
+use crate::traffic::{EdgeVolume, TrafficCount};
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+// Counts are placed mid-block on the street centerline, anything farther is another street
+const MAX_MATCH_DISTANCE: f64 = 50.0;
+
+// Below this name similarity an edge is not considered the counted street
+const MIN_NAME_SIMILARITY: f64 = 0.5;
+
+// Words every street name is made of; two names sharing only these are different streets
+const GENERIC_WORDS: &[&str] = &[
+    "STREET", "AVENUE", "ROAD", "DRIVE", "PLACE", "BOULEVARD", "PARKWAY", "BRIDGE", "EXPRESSWAY",
+    "HIGHWAY", "LANE", "TERRACE", "EAST", "WEST", "NORTH", "SOUTH", "THE", "OF",
+];
+
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("ST", "STREET"), ("AVE", "AVENUE"), ("AV", "AVENUE"), ("RD", "ROAD"), ("DR", "DRIVE"),
+    ("PL", "PLACE"), ("BLVD", "BOULEVARD"), ("PKWY", "PARKWAY"), ("BR", "BRIDGE"), ("BRG", "BRIDGE"),
+    ("EXPY", "EXPRESSWAY"), ("HWY", "HIGHWAY"), ("LN", "LANE"), ("E", "EAST"), ("W", "WEST"),
+    ("N", "NORTH"), ("S", "SOUTH"),
+];
+
+const ORDINAL_WORDS: &[(&str, &str)] = &[
+    ("FIRST", "1"), ("SECOND", "2"), ("THIRD", "3"), ("FOURTH", "4"), ("FIFTH", "5"), ("SIXTH", "6"),
+    ("SEVENTH", "7"), ("EIGHTH", "8"), ("NINTH", "9"), ("TENTH", "10"), ("ELEVENTH", "11"), ("TWELFTH", "12"),
+];
+
+/// Hourly volumes of one counted street segment in one travel direction,
+/// averaged over every day it was counted.
+#[derive(Clone, Debug)]
+pub struct CountedSegment {
+    pub segment_id: i32,
+    pub direction: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub street: String,
+    pub from_street: String,
+    pub to_street: String,
+    pub volumes: [Option<f64>; 24],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    High,
+    Medium,
+    Low,
+}
+
+impl Confidence {
+    fn from_score(score: f64) -> Confidence {
+        if score >= 0.7 {
+            Confidence::High
+        } else if score >= 0.4 {
+            Confidence::Medium
+        } else {
+            Confidence::Low
+        }
+    }
+}
+
+/// Directed car edges one counted segment was assigned to.
+#[derive(Clone, Debug, Serialize)]
+pub struct SegmentMatch {
+    pub segment_id: i32,
+    pub direction: String,
+    pub street: String,
+    pub way_id: i64,
+    pub way_name: Option<String>,
+    pub edges: Vec<(i64, i64)>,
+    // Meters from the count location to the closest matched edge
+    pub distance: f64,
+    pub name_similarity: f64,
+    // Name similarity scaled down with distance, 0 to 1
+    pub score: f64,
+    pub confidence: Confidence,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UnmatchedSegment {
+    pub segment_id: i32,
+    pub direction: String,
+    pub street: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MatchReport {
+    pub matches: Vec<SegmentMatch>,
+    pub unmatched: Vec<UnmatchedSegment>,
+}
+
+// Running (sum, count) of the volumes of each hour
+type HourlySums = [(f64, usize); 24];
+
+/// Average the hourly counts of every (segment, direction) over the days they cover.
+pub fn group_counts(counts: &[TrafficCount]) -> Vec<CountedSegment> {
+    let mut grouped: BTreeMap<(i32, String), (CountedSegment, HourlySums)> = BTreeMap::new();
+    for count in counts {
+        if !(0..24).contains(&count.hour) {
+            continue;
+        }
+        let (_, sums) = grouped
+            .entry((count.segment_id, count.direction.clone()))
+            .or_insert_with(|| {
+                let segment = CountedSegment {
+                    segment_id: count.segment_id,
+                    direction: count.direction.clone(),
+                    lat: count.lat,
+                    lon: count.lon,
+                    street: count.street.clone(),
+                    from_street: count.from_street.clone(),
+                    to_street: count.to_street.clone(),
+                    volumes: [None; 24],
+                };
+                (segment, [(0.0, 0); 24])
+            });
+        sums[count.hour as usize].0 += count.volume as f64;
+        sums[count.hour as usize].1 += 1;
+    }
+
+    grouped
+        .into_values()
+        .map(|(mut segment, sums)| {
+            for (volume, (sum, n)) in segment.volumes.iter_mut().zip(sums) {
+                if n > 0 {
+                    *volume = Some(sum / n as f64);
+                }
+            }
+            segment
+        })
+        .collect()
+}
+
+// Uppercase words of a street name with abbreviations expanded, ordinals as plain
+// numbers and spelled-out initials ("F D R") joined back together
+pub fn normalize_street_name(name: &str) -> Vec<String> {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_uppercase() } else { ' ' })
+        .collect();
+
+    let mut words: Vec<String> = Vec::new();
+    let mut initials = String::new();
+    for word in cleaned.split_whitespace() {
+        if word.len() == 1 && word.chars().all(|c| c.is_ascii_alphabetic()) {
+            initials.push_str(word);
+            continue;
+        }
+        if !initials.is_empty() {
+            words.push(std::mem::take(&mut initials));
+        }
+        words.push(word.to_string());
+    }
+    if !initials.is_empty() {
+        words.push(initials);
+    }
+
+    words
+        .into_iter()
+        .map(|word| {
+            if let Some((_, full)) = ABBREVIATIONS.iter().chain(ORDINAL_WORDS).find(|(short, _)| *short == word) {
+                return full.to_string();
+            }
+            // 58TH -> 58, 3RD -> 3
+            let digits: String = word.chars().take_while(|c| c.is_ascii_digit()).collect();
+            let suffix = &word[digits.len()..];
+            if !digits.is_empty() && ["ST", "ND", "RD", "TH"].contains(&suffix) {
+                return digits;
+            }
+            word
+        })
+        .collect()
+}
+
+fn jaccard(a: &HashSet<&str>, b: &HashSet<&str>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// How likely two street names mean the same street, 0 to 1. Distinctive words
+/// ("58", "BROADWAY") dominate; generic ones ("EAST", "STREET") only break ties.
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize_street_name(a), normalize_street_name(b));
+    let all_a: HashSet<&str> = a.iter().map(String::as_str).collect();
+    let all_b: HashSet<&str> = b.iter().map(String::as_str).collect();
+    let distinctive_a: HashSet<&str> = all_a.iter().copied().filter(|word| !GENERIC_WORDS.contains(word)).collect();
+    let distinctive_b: HashSet<&str> = all_b.iter().copied().filter(|word| !GENERIC_WORDS.contains(word)).collect();
+
+    if distinctive_a.is_empty() && distinctive_b.is_empty() {
+        return jaccard(&all_a, &all_b);
+    }
+    0.75 * jaccard(&distinctive_a, &distinctive_b) + 0.25 * jaccard(&all_a, &all_b)
+}
+
+// Unit vector (east, north) of a count direction
+fn direction_vector(direction: &str) -> Option<(f64, f64)> {
+    match direction {
+        "NB" => Some((0.0, 1.0)),
+        "SB" => Some((0.0, -1.0)),
+        "EB" => Some((1.0, 0.0)),
+        "WB" => Some((-1.0, 0.0)),
+        _ => None,
+    }
+}
+
+struct Candidate {
+    from: i64,
+    to: i64,
+    way_id: i64,
+    distance: f64,
+    name_similarity: f64,
+}
+
+// Continue from the matched edge along the same way and direction until the next
+// intersection, so the count covers the whole block and not just one edge of it
+fn extend_along_way(graph: &Graph, from: i64, to: i64, way_id: i64) -> Vec<(i64, i64)> {
+    let same_way = |a: i64, b: i64| graph.edge_way(TravelMode::Car, a, b).map(|way| way.id) == Some(way_id);
+    let is_intersection = |id: i64| graph.get_node(id).map(|node| node.adjacency_list.len() > 2).unwrap_or(true);
+
+    let mut edges = vec![(from, to)];
+    let mut seen: HashSet<i64> = HashSet::from([from, to]);
+
+    // Forward from `to`
+    let (mut previous, mut current) = (from, to);
+    while !is_intersection(current) {
+        let Some(node) = graph.get_node(current) else { break };
+        let Some(&next) = node
+            .adjacency(TravelMode::Car)
+            .iter()
+            .find(|next| **next != previous && !seen.contains(next) && same_way(current, **next))
+        else {
+            break;
+        };
+        edges.push((current, next));
+        seen.insert(next);
+        (previous, current) = (current, next);
+    }
+
+    // Backward from `from`, through the nodes that lead into it
+    let (mut next, mut current) = (to, from);
+    while !is_intersection(current) {
+        let Some(node) = graph.get_node(current) else { break };
+        let Some(&before) = node.reverse_adjacency(TravelMode::Car).iter().find(|before| {
+            **before != next
+                && !seen.contains(before)
+                && same_way(**before, current)
+                && graph
+                    .get_node(**before)
+                    .map(|before| before.adjacency(TravelMode::Car).contains(&current))
+                    .unwrap_or(false)
+        }) else {
+            break;
+        };
+        edges.insert(0, (before, current));
+        seen.insert(before);
+        (next, current) = (current, before);
+    }
+
+    edges
+}
+
+/// Assign every counted segment to the directed car edges it most likely describes:
+/// the closest edge within `MAX_MATCH_DISTANCE` heading the counted way whose OSM
+/// name matches the counted street, extended to the whole block.
+pub fn match_segments(graph: &Graph, segments: &[CountedSegment]) -> MatchReport {
+    let mut report = MatchReport::default();
+
+    for segment in segments {
+        let unmatched = |reason: String| UnmatchedSegment {
+            segment_id: segment.segment_id,
+            direction: segment.direction.clone(),
+            street: segment.street.clone(),
+            reason,
+        };
+        let heading = direction_vector(&segment.direction);
+
+        let (mut in_range, mut nearby) = (0, 0);
+        let mut closest_name: Option<(f64, String)> = None;
+        let mut best: Option<Candidate> = None;
+        for node in graph.nodes() {
+            for &next_id in node.adjacency(TravelMode::Car) {
+                let Some(next) = graph.get_node(next_id) else { continue };
+                let geometry = graph
+                    .edge_geometry(TravelMode::Car, node.id, next_id)
+                    .unwrap_or_else(|| vec![[node.lon, node.lat], [next.lon, next.lat]]);
+                let points: Vec<(f64, f64)> = geometry
+                    .iter()
+                    .map(|[lon, lat]| to_local(segment.lat, segment.lon, *lat, *lon))
+                    .collect();
+                let distance = points
+                    .windows(2)
+                    .map(|pair| project_onto_segment(pair[0], pair[1]).0)
+                    .fold(f64::INFINITY, f64::min);
+                if distance > MAX_MATCH_DISTANCE {
+                    continue;
+                }
+                in_range += 1;
+
+                // The edge has to run the counted way, not against it
+                let (start, end) = (points[0], points[points.len() - 1]);
+                if let Some((east, north)) = heading {
+                    if (end.0 - start.0) * east + (end.1 - start.1) * north <= 0.0 {
+                        continue;
+                    }
+                }
+                nearby += 1;
+
+                let Some(way) = graph.edge_way(TravelMode::Car, node.id, next_id) else { continue };
+                let similarity = way
+                    .name
+                    .as_deref()
+                    .map(|name| name_similarity(&segment.street, name))
+                    .unwrap_or(0.0);
+                if let Some(name) = &way.name {
+                    if closest_name.as_ref().map(|(d, _)| distance < *d).unwrap_or(true) {
+                        closest_name = Some((distance, name.clone()));
+                    }
+                }
+                if similarity < MIN_NAME_SIMILARITY {
+                    continue;
+                }
+
+                let better = match &best {
+                    None => true,
+                    Some(best) => (similarity, -distance) > (best.name_similarity, -best.distance),
+                };
+                if better {
+                    best = Some(Candidate { from: node.id, to: next_id, way_id: way.id, distance, name_similarity: similarity });
+                }
+            }
+        }
+
+        let Some(best) = best else {
+            let reason = match closest_name {
+                _ if in_range == 0 => format!("no car edge within {} m", MAX_MATCH_DISTANCE),
+                _ if nearby == 0 => format!(
+                    "no car edge heading {} within {} m",
+                    segment.direction, MAX_MATCH_DISTANCE
+                ),
+                Some((distance, name)) => format!(
+                    "no edge named like '{}' within {} m, closest is '{}' at {:.0} m",
+                    segment.street, MAX_MATCH_DISTANCE, name, distance
+                ),
+                None => format!("{} nearby edges but none has a name", nearby),
+            };
+            report.unmatched.push(unmatched(reason));
+            continue;
+        };
+
+        let score = best.name_similarity * (1.0 - best.distance / MAX_MATCH_DISTANCE);
+        report.matches.push(SegmentMatch {
+            segment_id: segment.segment_id,
+            direction: segment.direction.clone(),
+            street: segment.street.clone(),
+            way_id: best.way_id,
+            way_name: graph.edge_way(TravelMode::Car, best.from, best.to).and_then(|way| way.name.clone()),
+            edges: extend_along_way(graph, best.from, best.to, best.way_id),
+            distance: best.distance,
+            name_similarity: best.name_similarity,
+            score,
+            confidence: Confidence::from_score(score),
+        });
+    }
+
+    report
+}
+
+/// Hourly volumes per matched edge, the rows of `edge_traffic`.
+pub fn edge_volumes(segments: &[CountedSegment], matches: &[SegmentMatch]) -> Vec<EdgeVolume> {
+    let mut volumes = Vec::new();
+    for segment_match in matches {
+        let Some(segment) = segments
+            .iter()
+            .find(|segment| segment.segment_id == segment_match.segment_id && segment.direction == segment_match.direction)
+        else {
+            continue;
+        };
+        for &(source, target) in &segment_match.edges {
+            for (hour, volume) in segment.volumes.iter().enumerate() {
+                if let Some(volume) = volume {
+                    volumes.push(EdgeVolume { source, target, hour: hour as i32, volume: *volume });
+                }
+            }
+        }
+    }
+    volumes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::node;
+    use crate::ways::OsmWay;
+
+    fn segment(direction: &str, lat: f64, lon: f64) -> CountedSegment {
+        CountedSegment {
+            segment_id: 1,
+            direction: direction.to_string(),
+            lat,
+            lon,
+            street: "5 AVENUE".to_string(),
+            from_street: String::new(),
+            to_street: String::new(),
+            volumes: [None; 24],
+        }
+    }
+
+    fn matching_edges(graph: &Graph, segment: CountedSegment) -> Option<(i64, Vec<(i64, i64)>)> {
+        let report = match_segments(graph, &[segment]);
+        report.matches.first().map(|segment_match| (segment_match.way_id, segment_match.edges.clone()))
+    }
+
+    #[test]
+    fn street_names_normalize() {
+        assert_eq!(normalize_street_name("E 58th St"), ["EAST", "58", "STREET"]);
+        assert_eq!(normalize_street_name("EAST 58 STREET"), ["EAST", "58", "STREET"]);
+        assert_eq!(normalize_street_name("Third Ave."), ["3", "AVENUE"]);
+        assert_eq!(normalize_street_name("F D R DRIVE"), ["FDR", "DRIVE"]);
+        assert_eq!(normalize_street_name("FDR Drive"), ["FDR", "DRIVE"]);
+    }
+
+    // Street names as written by NYC DOT against their OSM counterparts
+    #[test]
+    fn name_similarity_separates_streets() {
+        let cases = [
+            ("EAST 58 STREET", "East 58th Street", true),
+            ("3 AVENUE", "3rd Avenue", true),
+            ("F D R DRIVE", "FDR Drive", true),
+            ("97 ST TRANSVERSE", "97th Street Transverse", true),
+            ("ED KOCH QUEENSBORO BRIDGE", "Ed Koch Queensboro Bridge", true),
+            ("CENTRAL PARK WEST", "Central Park West", true),
+            ("BROADWAY", "Broadway", true),
+            ("EAST 58 STREET", "East 59th Street", false),
+            ("3 AVENUE", "2nd Avenue", false),
+            ("EAST 116 STREET", "Pleasant Avenue", false),
+            ("YORK AVENUE", "East 61st Street", false),
+        ];
+        for (count_name, osm_name, expected) in cases {
+            let similarity = name_similarity(count_name, osm_name);
+            assert_eq!(
+                similarity >= MIN_NAME_SIMILARITY,
+                expected,
+                "'{}' vs '{}' scored {:.2}",
+                count_name,
+                osm_name,
+                similarity
+            );
+        }
+    }
+
+    // Two parallel two-way blocks of 5th Avenue about 26 m apart, running north
+    #[test]
+    fn matches_the_closest_edge_heading_the_counted_way() {
+        let mut graph = Graph::from_nodes(vec![
+            node(1, 40.0, -74.0, vec![2]),
+            node(2, 40.001, -74.0, vec![1]),
+            node(3, 40.0, -73.9997, vec![4]),
+            node(4, 40.001, -73.9997, vec![3]),
+        ]);
+        let tags = Some(vec!["name".to_string(), "5th Avenue".to_string()]);
+        graph.set_ways(&[
+            OsmWay { id: 10, nodes: vec![1, 2], tags: tags.clone() },
+            OsmWay { id: 11, nodes: vec![3, 4], tags },
+        ]);
+
+        // 6 m from the western block, 20 m from the eastern one
+        let west = matching_edges(&graph, segment("NB", 40.0005, -73.99993));
+        assert_eq!(west, Some((10, vec![(1, 2)])));
+        let west = matching_edges(&graph, segment("SB", 40.0005, -73.99993));
+        assert_eq!(west, Some((10, vec![(2, 1)])));
+        let east = matching_edges(&graph, segment("NB", 40.0005, -73.99977));
+        assert_eq!(east, Some((11, vec![(3, 4)])));
+
+        let report = match_segments(&graph, &[segment("NB", 40.01, -74.0)]);
+        assert!(report.matches.is_empty());
+        assert_eq!(report.unmatched[0].reason, "no car edge within 50 m");
+    }
+}
@@ -0,0 +1,56 @@
+use crate::database::RawNode;
+use crate::dijkstra::{edge_weight, explore};
+use crate::mode::TravelMode;
+use crate::source::GraphSource;
+
+use serde::Serialize;
+
+use lambda_runtime::tracing::info;
+
+/// Shortest-path distances from every origin to every destination.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DistanceMatrix {
+    /// `distances[i][j]` in meters from origin i to destination j, None when unreachable.
+    pub distances: Vec<Vec<Option<f64>>>,
+    pub nodes_expanded: usize,
+    pub timed_out: bool,
+}
+
+// One one-to-many Dijkstra per origin, each stopping once every destination is settled.
+// Points that could not be snapped (None) get unreachable rows or columns.
+pub async fn distance_matrix<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    origins: &[Option<RawNode>],
+    destinations: &[Option<RawNode>],
+) -> DistanceMatrix {
+    info!("Distance matrix started for {:?}: {} x {}", mode, origins.len(), destinations.len());
+
+    let mut target_ids: Vec<i64> = destinations.iter().flatten().map(|node| node.id).collect();
+    target_ids.sort_unstable();
+    target_ids.dedup();
+
+    let mut matrix = DistanceMatrix::default();
+    for origin in origins {
+        let Some(origin) = origin else {
+            matrix.distances.push(vec![None; destinations.len()]);
+            continue;
+        };
+
+        let exploration =
+            explore(source, mode, origin, &target_ids, f64::MAX, |_| 0.0, |from, to, _| edge_weight(mode, from, to)).await;
+        matrix.nodes_expanded += exploration.expanded_count;
+        matrix.timed_out |= exploration.timed_out;
+
+        let row = destinations
+            .iter()
+            .map(|destination| {
+                let destination = destination.as_ref()?;
+                exploration.distances.get(&destination.id).copied()
+            })
+            .collect();
+        matrix.distances.push(row);
+    }
+
+    matrix
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// How a route is travelled; decides which of a node's adjacency lists the search expands.
+///
+/// Pedestrians may walk either way along any routable way, cars and bikes follow oneway tags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TravelMode {
+    #[default]
+    Foot,
+    Car,
+    Bike,
+}
+
+impl TravelMode {
+    /// Typical travel speed in meters per second, used to turn time budgets into distances.
+    pub fn speed_mps(self) -> f64 {
+        match self {
+            TravelMode::Foot => 1.4,  // ~5 km/h
+            TravelMode::Car => 8.3,   // ~30 km/h, city driving
+            TravelMode::Bike => 4.2,  // ~15 km/h
+        }
+    }
+}
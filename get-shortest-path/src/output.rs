@@ -0,0 +1,273 @@
+// Route responses in formats other tools read as is. The default JSON body is this
+// router's own layout; GeoJSON gives a FeatureCollection that GIS tools and Leaflet's
+// L.geoJSON load directly, GPX goes onto handheld GPS units and KML into Google Earth.
+
+use crate::alternatives::AlternativeRoute;
+use crate::{ResolvedWaypoint, Route};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt::Write;
+
+/// Response format named by the `output` field of a route request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    GeoJson,
+    Gpx,
+    Kml,
+}
+
+impl OutputFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "application/json",
+            OutputFormat::GeoJson => "application/geo+json",
+            OutputFormat::Gpx => "application/gpx+xml",
+            OutputFormat::Kml => "application/vnd.google-earth.kml+xml",
+        }
+    }
+}
+
+fn feature(geometry: Value, properties: Value) -> Value {
+    json!({ "type": "Feature", "geometry": geometry, "properties": properties })
+}
+
+fn point(coordinates: [f64; 2]) -> Value {
+    json!({ "type": "Point", "coordinates": coordinates })
+}
+
+// A LineString needs two positions, an empty path has no geometry at all
+fn line_string(path: &[[f64; 2]]) -> Value {
+    if path.len() < 2 {
+        return Value::Null;
+    }
+    json!({ "type": "LineString", "coordinates": path })
+}
+
+/// FeatureCollection for a route: the path as a LineString, one Point per requested
+/// waypoint and one per snapped position, then any alternative routes. Every feature
+/// names what it is in its `kind` property. `properties` is merged into the route's own.
+/// With `order`, snapped points are in visiting order and refer back to their waypoint.
+pub fn route_geojson(
+    route: &Route,
+    properties: Map<String, Value>,
+    waypoints: &[ResolvedWaypoint],
+    order: Option<&[usize]>,
+    alternatives: Option<&[AlternativeRoute]>,
+) -> Value {
+    let mut route_properties = properties;
+    route_properties.insert("kind".to_string(), json!("route"));
+    route_properties.insert("distance".to_string(), json!(route.distance));
+    route_properties.insert("duration".to_string(), json!(route.duration));
+    route_properties.insert("legs".to_string(), json!(route.legs));
+    let mut features = vec![feature(line_string(&route.path), Value::Object(route_properties))];
+
+    for (index, waypoint) in waypoints.iter().enumerate() {
+        features.push(feature(
+            point(waypoint.location),
+            json!({ "kind": "waypoint", "index": index, "name": waypoint.name }),
+        ));
+    }
+
+    for (index, snapped) in route.snapped.iter().enumerate() {
+        let waypoint = order.map_or(index, |order| order[index]);
+        features.push(feature(
+            point(snapped.point),
+            json!({
+                "kind": "snapped",
+                "waypoint": waypoint,
+                "distance": snapped.distance,
+                "node_id": snapped.node_id,
+                "edge": snapped.edge,
+            }),
+        ));
+    }
+
+    for (rank, alternative) in alternatives.unwrap_or_default().iter().enumerate() {
+        features.push(feature(
+            line_string(&alternative.path),
+            json!({
+                "kind": "alternative",
+                "rank": rank,
+                "distance": alternative.distance,
+                "overlap": alternative.overlap,
+            }),
+        ));
+    }
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+// Text and attribute values, names come straight from OSM tags
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Waypoints in the order the route visits them
+fn visited<'a>(waypoints: &'a [ResolvedWaypoint], order: Option<&[usize]>) -> Vec<&'a ResolvedWaypoint> {
+    match order {
+        Some(order) => order.iter().map(|index| &waypoints[*index]).collect(),
+        None => waypoints.iter().collect(),
+    }
+}
+
+// "Kung Fu Tea to Guyot Hall" when both ends were given by name
+fn route_name(visited: &[&ResolvedWaypoint]) -> String {
+    match (visited.first().and_then(|first| first.name.as_ref()), visited.last().and_then(|last| last.name.as_ref())) {
+        (Some(start), Some(end)) => format!("{} to {}", start, end),
+        _ => "Route".to_string(),
+    }
+}
+
+/// GPX 1.1 document: the waypoints as a `rte` of `rtept`s, named when they were given by
+/// name, and the full path as a `trk` of `trkpt`s.
+pub fn route_gpx(route: &Route, waypoints: &[ResolvedWaypoint], order: Option<&[usize]>) -> String {
+    let visited = visited(waypoints, order);
+    let name = escape_xml(&route_name(&visited));
+
+    let mut gpx = String::new();
+    gpx.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    gpx.push_str("<gpx version=\"1.1\" creator=\"get-shortest-path\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n");
+
+    let _ = writeln!(gpx, "  <rte>\n    <name>{}</name>", name);
+    for waypoint in &visited {
+        let [lon, lat] = waypoint.location;
+        match &waypoint.name {
+            Some(name) => {
+                let _ = writeln!(
+                    gpx,
+                    "    <rtept lat=\"{:.7}\" lon=\"{:.7}\"><name>{}</name></rtept>",
+                    lat,
+                    lon,
+                    escape_xml(name)
+                );
+            }
+            None => {
+                let _ = writeln!(gpx, "    <rtept lat=\"{:.7}\" lon=\"{:.7}\"/>", lat, lon);
+            }
+        }
+    }
+    gpx.push_str("  </rte>\n");
+
+    let _ = writeln!(gpx, "  <trk>\n    <name>{}</name>\n    <trkseg>", name);
+    for [lon, lat] in &route.path {
+        let _ = writeln!(gpx, "      <trkpt lat=\"{:.7}\" lon=\"{:.7}\"/>", lat, lon);
+    }
+    gpx.push_str("    </trkseg>\n  </trk>\n</gpx>\n");
+    gpx
+}
+
+/// KML document with the path as a LineString placemark and one Point placemark per
+/// waypoint, named when it was given by name.
+pub fn route_kml(route: &Route, waypoints: &[ResolvedWaypoint], order: Option<&[usize]>) -> String {
+    let visited = visited(waypoints, order);
+    let name = escape_xml(&route_name(&visited));
+
+    let mut kml = String::new();
+    kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    kml.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+    let _ = writeln!(kml, "  <name>{}</name>", name);
+
+    let coordinates: Vec<String> = route.path.iter().map(|[lon, lat]| format!("{:.7},{:.7}", lon, lat)).collect();
+    let _ = writeln!(
+        kml,
+        "  <Placemark>\n    <name>{}</name>\n    <description>{:.0} m, {:.0} s</description>",
+        name, route.distance, route.duration
+    );
+    let _ = writeln!(
+        kml,
+        "    <LineString>\n      <tessellate>1</tessellate>\n      <coordinates>{}</coordinates>\n    </LineString>\n  </Placemark>",
+        coordinates.join(" ")
+    );
+
+    for (index, waypoint) in visited.iter().enumerate() {
+        let [lon, lat] = waypoint.location;
+        let label = match &waypoint.name {
+            Some(name) => escape_xml(name),
+            None => format!("Waypoint {}", index + 1),
+        };
+        let _ = writeln!(
+            kml,
+            "  <Placemark>\n    <name>{}</name>\n    <Point><coordinates>{:.7},{:.7}</coordinates></Point>\n  </Placemark>",
+            label, lon, lat
+        );
+    }
+    kml.push_str("</Document>\n</kml>\n");
+    kml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "Bob & <Sons> \"Hardware\" 'n' Tools";
+
+    fn unescape_xml(text: &str) -> String {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+
+    // Text of every <name> element, as an XML reader would return it
+    fn names(document: &str) -> Vec<String> {
+        document
+            .split("<name>")
+            .skip(1)
+            .map(|rest| unescape_xml(&rest[..rest.find("</name>").unwrap()]))
+            .collect()
+    }
+
+    fn trip() -> (Route, Vec<ResolvedWaypoint>) {
+        let route = Route {
+            path: vec![[-74.6594, 40.3490], [-74.6593, 40.3487]],
+            distance: 35.0,
+            duration: 25.0,
+            ..Route::default()
+        };
+        let waypoints = vec![
+            ResolvedWaypoint { name: Some(NAME.to_string()), location: [-74.6594, 40.3490] },
+            ResolvedWaypoint { name: Some("Nassau Hall".to_string()), location: [-74.6593, 40.3487] },
+        ];
+        (route, waypoints)
+    }
+
+    #[test]
+    fn escapes_markup_characters() {
+        assert_eq!(escape_xml(NAME), "Bob &amp; &lt;Sons&gt; &quot;Hardware&quot; &apos;n&apos; Tools");
+        assert_eq!(unescape_xml(&escape_xml("&amp; is already escaped")), "&amp; is already escaped");
+    }
+
+    #[test]
+    fn gpx_names_round_trip() {
+        let (route, waypoints) = trip();
+        let gpx = route_gpx(&route, &waypoints, None);
+        assert!(!gpx.contains("<Sons>") && !gpx.contains("Bob & "));
+        let route_name = format!("{} to Nassau Hall", NAME);
+        assert_eq!(names(&gpx), [route_name.as_str(), NAME, "Nassau Hall", route_name.as_str()]);
+    }
+
+    #[test]
+    fn kml_names_round_trip() {
+        let (route, waypoints) = trip();
+        let kml = route_kml(&route, &waypoints, Some(&[1, 0]));
+        assert!(!kml.contains("<Sons>") && !kml.contains("Bob & "));
+        let route_name = format!("Nassau Hall to {}", NAME);
+        assert_eq!(names(&kml), [route_name.as_str(), route_name.as_str(), "Nassau Hall", NAME]);
+    }
+}
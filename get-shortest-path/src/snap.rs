@@ -0,0 +1,574 @@
+// Snapping onto the nearest routable edge. The input coordinate is projected onto the
+// closest edge `mode` may use and, unless it lands on a vertex, becomes a virtual node
+// splitting that edge. Searches then run over a `SnapOverlay`, which adds the virtual
+// nodes and their links on top of any other graph source.
+
+use crate::alt::LandmarkTables;
+use crate::ch::ContractionHierarchy;
+use crate::database::RawNode;
+use crate::dijkstra::edge_weight;
+use crate::geocode::Geocoder;
+use crate::mode::TravelMode;
+use crate::source::GraphSource;
+use crate::traffic::TrafficModel;
+use crate::ways::WayTags;
+
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+pub(crate) const METERS_PER_DEGREE_LATITUDE: f64 = 111_320.0;
+
+// Projections closer than this to an edge end snap onto that vertex instead
+const SNAP_TO_NODE_METERS: f64 = 1.0;
+
+/// Virtual nodes get negative ids, OSM ids are always positive.
+pub fn is_virtual(id: i64) -> bool {
+    id < 0
+}
+
+// Local east / north meters around (lat0, lon0), exact enough within a few hundred meters
+pub(crate) fn to_local(lat0: f64, lon0: f64, lat: f64, lon: f64) -> (f64, f64) {
+    let x = (lon - lon0).to_radians() * lat0.to_radians().cos() * EARTH_RADIUS_METERS;
+    let y = (lat - lat0).to_radians() * EARTH_RADIUS_METERS;
+    (x, y)
+}
+
+// Straight-line meters between two nearby coordinates
+pub(crate) fn local_distance(lat0: f64, lon0: f64, lat: f64, lon: f64) -> f64 {
+    let (x, y) = to_local(lat0, lon0, lat, lon);
+    (x * x + y * y).sqrt()
+}
+
+// Closest point to the origin on the segment a-b, all in local meters: (distance, t)
+pub(crate) fn project_onto_segment(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        (-(a.0 * dx + a.1 * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (x, y) = (a.0 + t * dx, a.1 + t * dy);
+    ((x * x + y * y).sqrt(), t)
+}
+
+/// Where an input coordinate ended up on the graph.
+#[derive(Clone, Debug, Serialize)]
+pub struct SnappedPoint {
+    /// Snapped position as [longitude, latitude].
+    pub point: [f64; 2],
+    /// Meters between the input coordinate and the snapped position.
+    pub distance: f64,
+    /// The graph node when the point snapped onto a vertex.
+    pub node_id: Option<i64>,
+    /// The edge split by the virtual node otherwise.
+    pub edge: Option<(i64, i64)>,
+}
+
+/// Projection of a coordinate onto one edge.
+#[derive(Clone, Debug)]
+pub struct EdgeSnap {
+    pub from: RawNode,
+    pub to: RawNode,
+    // Full edge shape from `from` to `to`, [lon, lat] pairs
+    geometry: Vec<[f64; 2]>,
+    // The point lies on geometry[segment]..geometry[segment + 1], at fraction t
+    segment: usize,
+    t: f64,
+    // Position along the edge in stored-length meters, and the stored length
+    offset: f64,
+    length: f64,
+    pub point: [f64; 2],
+    pub distance: f64,
+}
+
+impl EdgeSnap {
+    // Snapped onto an end of the edge, no virtual node needed
+    fn vertex(&self) -> Option<&RawNode> {
+        if self.offset < SNAP_TO_NODE_METERS {
+            Some(&self.from)
+        } else if self.length - self.offset < SNAP_TO_NODE_METERS {
+            Some(&self.to)
+        } else {
+            None
+        }
+    }
+
+    pub fn snapped_point(&self) -> SnappedPoint {
+        match self.vertex() {
+            Some(node) => SnappedPoint {
+                point: [node.lon, node.lat],
+                distance: self.distance,
+                node_id: Some(node.id),
+                edge: None,
+            },
+            None => SnappedPoint {
+                point: self.point,
+                distance: self.distance,
+                node_id: None,
+                edge: Some((self.from.id, self.to.id)),
+            },
+        }
+    }
+
+    // Shape from the start of the edge up to the snapped point
+    fn geometry_before(&self) -> Vec<[f64; 2]> {
+        let mut geometry = self.geometry[..=self.segment].to_vec();
+        geometry.push(self.point);
+        geometry
+    }
+
+    // Shape from the snapped point to the end of the edge
+    fn geometry_after(&self) -> Vec<[f64; 2]> {
+        let mut geometry = vec![self.point];
+        geometry.extend_from_slice(&self.geometry[self.segment + 1..]);
+        geometry
+    }
+}
+
+/// Closest of `edges` to (latitude, longitude), each given as (from, to) in a direction
+/// the search may travel. `geometry` returns stored edge shapes when known.
+pub(crate) fn nearest_edge<'a>(
+    mode: TravelMode,
+    edges: impl Iterator<Item = (&'a RawNode, &'a RawNode)>,
+    geometry: impl Fn(i64, i64) -> Option<Vec<[f64; 2]>>,
+    latitude: f64,
+    longitude: f64,
+) -> Option<EdgeSnap> {
+    let shape = |from: &RawNode, to: &RawNode| {
+        geometry(from.id, to.id).unwrap_or_else(|| vec![[from.lon, from.lat], [to.lon, to.lat]])
+    };
+
+    // Closest edge as (from, to, shape segment, fraction along it, distance)
+    let mut best: Option<(&RawNode, &RawNode, usize, f64, f64)> = None;
+    for (from, to) in edges {
+        let local: Vec<(f64, f64)> = shape(from, to)
+            .iter()
+            .map(|[lon, lat]| to_local(latitude, longitude, *lat, *lon))
+            .collect();
+        for (segment, pair) in local.windows(2).enumerate() {
+            let (distance, t) = project_onto_segment(pair[0], pair[1]);
+            if best.map(|best| distance < best.4).unwrap_or(true) {
+                best = Some((from, to, segment, t, distance));
+            }
+        }
+    }
+
+    let (from, to, segment, t, distance) = best?;
+    let geometry = shape(from, to);
+    let (a, b) = (geometry[segment], geometry[segment + 1]);
+    let point = [a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1])];
+
+    // Offset along the shape, rescaled to the stored length searches add up
+    let lengths: Vec<f64> = geometry
+        .windows(2)
+        .map(|pair| local_distance(pair[0][1], pair[0][0], pair[1][1], pair[1][0]))
+        .collect();
+    let shape_length: f64 = lengths.iter().sum();
+    let shape_offset = lengths[..segment].iter().sum::<f64>() + t * lengths[segment];
+    let length = edge_weight(mode, from, to);
+    let offset = if shape_length > 0.0 { shape_offset / shape_length * length } else { 0.0 };
+
+    Some(EdgeSnap {
+        from: from.clone(),
+        to: to.clone(),
+        geometry,
+        segment,
+        t,
+        offset,
+        length,
+        point,
+        distance,
+    })
+}
+
+// Arc between a real node and a virtual one, seen from the real node
+#[derive(Clone, Debug)]
+struct Link {
+    virtual_id: i64,
+    length: f64,
+    // Per mode: may travel real -> virtual, may travel virtual -> real
+    car: (bool, bool),
+    bike: (bool, bool),
+}
+
+fn push_unique(list: &mut Vec<i64>, id: i64) {
+    if !list.contains(&id) {
+        list.push(id);
+    }
+}
+
+/// Graph source with virtual nodes for snapped points layered over `inner`.
+pub struct SnapOverlay<'a, S> {
+    inner: &'a S,
+    virtual_nodes: HashMap<i64, RawNode>,
+    links: HashMap<i64, Vec<Link>>,
+    geometries: HashMap<(i64, i64), Vec<[f64; 2]>>,
+    endpoints: Vec<RawNode>,
+}
+
+impl<'a, S: GraphSource> SnapOverlay<'a, S> {
+    /// Add one virtual node for every snap that does not land on a vertex.
+    /// Snaps sharing an edge are also linked to each other directly.
+    pub fn new(inner: &'a S, snaps: &[EdgeSnap]) -> SnapOverlay<'a, S> {
+        let mut overlay = SnapOverlay {
+            inner,
+            virtual_nodes: HashMap::new(),
+            links: HashMap::new(),
+            geometries: HashMap::new(),
+            endpoints: Vec::with_capacity(snaps.len()),
+        };
+
+        let mut on_edges: Vec<(i64, &EdgeSnap)> = Vec::new();
+        for (i, snap) in snaps.iter().enumerate() {
+            if snap.vertex().is_some() {
+                continue;
+            }
+            let id = -(i as i64) - 1;
+            overlay.add_virtual_node(id, snap);
+
+            for (other_id, other) in &on_edges {
+                overlay.link_virtual_nodes(*other_id, other, id, snap);
+            }
+            on_edges.push((id, snap));
+        }
+
+        for (i, snap) in snaps.iter().enumerate() {
+            let endpoint = match snap.vertex() {
+                Some(node) => overlay.patch(node.clone()),
+                None => overlay.virtual_nodes[&(-(i as i64) - 1)].clone(),
+            };
+            overlay.endpoints.push(endpoint);
+        }
+        overlay
+    }
+
+    /// Search node for the `index`-th snap, virtual or real.
+    pub fn endpoint(&self, index: usize) -> RawNode {
+        self.endpoints[index].clone()
+    }
+
+    fn add_virtual_node(&mut self, id: i64, snap: &EdgeSnap) {
+        let (from, to) = (&snap.from, &snap.to);
+        let allows = |mode: TravelMode| (from.adjacency(mode).contains(&to.id), to.adjacency(mode).contains(&from.id));
+        let (car_forward, car_backward) = allows(TravelMode::Car);
+        let (bike_forward, bike_backward) = allows(TravelMode::Bike);
+
+        let directed = |forward: bool, backward: bool| {
+            let mut out = Vec::new();
+            let mut into = Vec::new();
+            if forward {
+                out.push(to.id);
+                into.push(from.id);
+            }
+            if backward {
+                out.push(from.id);
+                into.push(to.id);
+            }
+            (out, into)
+        };
+        let (car_out, car_in) = directed(car_forward, car_backward);
+        let (bike_out, bike_in) = directed(bike_forward, bike_backward);
+
+        // Walking lengths double as the stored lengths of the directed arcs
+        self.virtual_nodes.insert(
+            id,
+            RawNode {
+                id,
+                lon: snap.point[0],
+                lat: snap.point[1],
+                adjacency_list: vec![from.id, to.id],
+                adjacency_lengths: Some(vec![snap.offset, snap.length - snap.offset]),
+                car_adjacency_list: Some(car_out),
+                bike_adjacency_list: Some(bike_out),
+                car_adjacency_lengths: None,
+                bike_adjacency_lengths: None,
+                car_reverse_adjacency_list: Some(car_in),
+                bike_reverse_adjacency_list: Some(bike_in),
+            },
+        );
+
+        self.links.entry(from.id).or_default().push(Link {
+            virtual_id: id,
+            length: snap.offset,
+            car: (car_forward, car_backward),
+            bike: (bike_forward, bike_backward),
+        });
+        self.links.entry(to.id).or_default().push(Link {
+            virtual_id: id,
+            length: snap.length - snap.offset,
+            car: (car_backward, car_forward),
+            bike: (bike_backward, bike_forward),
+        });
+
+        let before = snap.geometry_before();
+        let after = snap.geometry_after();
+        self.geometries.insert((from.id, id), before.clone());
+        self.geometries.insert((id, from.id), before.into_iter().rev().collect());
+        self.geometries.insert((id, to.id), after.clone());
+        self.geometries.insert((to.id, id), after.into_iter().rev().collect());
+    }
+
+    // Two snaps on the same edge reach each other without leaving it
+    fn link_virtual_nodes(&mut self, first_id: i64, first: &EdgeSnap, second_id: i64, second: &EdgeSnap) {
+        let same_direction = first.from.id == second.from.id && first.to.id == second.to.id;
+        let opposite = first.from.id == second.to.id && first.to.id == second.from.id;
+        if !same_direction && !opposite {
+            return;
+        }
+
+        // Positions along the first snap's edge: (fractional shape vertex, stored meters)
+        let first_position = (first.segment as f64 + first.t, first.offset);
+        let second_position = if same_direction {
+            (second.segment as f64 + second.t, second.offset)
+        } else {
+            let segment_count = second.geometry.len() - 1;
+            ((segment_count - 1 - second.segment) as f64 + 1.0 - second.t, second.length - second.offset)
+        };
+        let ((low, low_id, low_point), (high, high_id, high_point)) = if first_position.0 <= second_position.0 {
+            ((first_position.0, first_id, first.point), (second_position.0, second_id, second.point))
+        } else {
+            ((second_position.0, second_id, second.point), (first_position.0, first_id, first.point))
+        };
+        let length = (first_position.1 - second_position.1).abs();
+
+        let mut between = vec![low_point];
+        between.extend(
+            first.geometry[low.floor() as usize + 1..]
+                .iter()
+                .enumerate()
+                .take_while(|(index, _)| ((low.floor() as usize + 1 + index) as f64) < high)
+                .map(|(_, point)| *point),
+        );
+        between.push(high_point);
+        self.geometries.insert((low_id, high_id), between.clone());
+        self.geometries.insert((high_id, low_id), between.into_iter().rev().collect());
+
+        // Walking goes both ways, so the stored lengths cover every mode
+        for (a, b) in [(low_id, high_id), (high_id, low_id)] {
+            let node = self.virtual_nodes.get_mut(&a).unwrap();
+            node.adjacency_list.push(b);
+            if let Some(lengths) = node.adjacency_lengths.as_mut() {
+                lengths.push(length);
+            }
+        }
+
+        let (from, to) = (&first.from, &first.to);
+        for mode in [TravelMode::Car, TravelMode::Bike] {
+            let forward = from.adjacency(mode).contains(&to.id);
+            let backward = to.adjacency(mode).contains(&from.id);
+            for (allowed, a, b) in [(forward, low_id, high_id), (backward, high_id, low_id)] {
+                if allowed {
+                    let (out, _) = self.directed_lists(a, mode);
+                    push_unique(out, b);
+                    let (_, into) = self.directed_lists(b, mode);
+                    push_unique(into, a);
+                }
+            }
+        }
+    }
+
+    fn directed_lists(&mut self, virtual_id: i64, mode: TravelMode) -> (&mut Vec<i64>, &mut Vec<i64>) {
+        let node = self.virtual_nodes.get_mut(&virtual_id).unwrap();
+        let (out, into) = match mode {
+            TravelMode::Bike => (&mut node.bike_adjacency_list, &mut node.bike_reverse_adjacency_list),
+            _ => (&mut node.car_adjacency_list, &mut node.car_reverse_adjacency_list),
+        };
+        (out.get_or_insert_with(Vec::new), into.get_or_insert_with(Vec::new))
+    }
+
+    // Real node with the arcs to and from neighboring virtual nodes added
+    fn patch(&self, mut node: RawNode) -> RawNode {
+        let Some(links) = self.links.get(&node.id) else {
+            return node;
+        };
+        for link in links {
+            node.adjacency_list.push(link.virtual_id);
+            if let Some(lengths) = node.adjacency_lengths.as_mut() {
+                lengths.push(link.length);
+            }
+            for ((out, into), list, lengths, reverse) in [
+                (link.car, &mut node.car_adjacency_list, &mut node.car_adjacency_lengths, &mut node.car_reverse_adjacency_list),
+                (link.bike, &mut node.bike_adjacency_list, &mut node.bike_adjacency_lengths, &mut node.bike_reverse_adjacency_list),
+            ] {
+                // Without directed lists the walking list above already covers the mode
+                if let (true, Some(list)) = (out, list.as_mut()) {
+                    list.push(link.virtual_id);
+                    if let Some(lengths) = lengths.as_mut() {
+                        lengths.push(link.length);
+                    }
+                }
+                if let (true, Some(reverse)) = (into, reverse.as_mut()) {
+                    reverse.push(link.virtual_id);
+                }
+            }
+        }
+        node
+    }
+}
+
+impl<S: GraphSource> GraphSource for SnapOverlay<'_, S> {
+    fn frontier_batch_size(&self) -> usize {
+        self.inner.frontier_batch_size()
+    }
+
+    fn is_resident(&self) -> bool {
+        self.inner.is_resident()
+    }
+
+    // Only the few real nodes next to a snap need a patched copy
+    fn borrow_node(&self, id: i64) -> Option<Cow<'_, RawNode>> {
+        if let Some(node) = self.virtual_nodes.get(&id) {
+            return Some(Cow::Borrowed(node));
+        }
+        let node = self.inner.borrow_node(id)?;
+        if self.links.contains_key(&id) {
+            return Some(Cow::Owned(self.patch(node.into_owned())));
+        }
+        Some(node)
+    }
+
+    fn edge_geometry(&self, mode: TravelMode, from_id: i64, to_id: i64) -> Option<Vec<[f64; 2]>> {
+        match self.geometries.get(&(from_id, to_id)) {
+            Some(geometry) => Some(geometry.clone()),
+            None => self.inner.edge_geometry(mode, from_id, to_id),
+        }
+    }
+
+    // Arcs touching a virtual node lie on the edge it split
+    fn edge_way(&self, mode: TravelMode, from_id: i64, to_id: i64) -> Option<&WayTags> {
+        let split_edge = |id: i64| self.virtual_nodes.get(&id).map(|node| (node.adjacency_list[0], node.adjacency_list[1]));
+        match (split_edge(from_id), split_edge(to_id)) {
+            (Some((from, to)), _) | (None, Some((from, to))) => self.inner.edge_way(mode, from, to),
+            (None, None) => self.inner.edge_way(mode, from_id, to_id),
+        }
+    }
+
+    fn contraction_hierarchy(&self, mode: TravelMode) -> Option<&ContractionHierarchy> {
+        self.inner.contraction_hierarchy(mode)
+    }
+
+    fn landmark_tables(&self, mode: TravelMode) -> Option<&LandmarkTables> {
+        self.inner.landmark_tables(mode)
+    }
+
+    fn traffic_model(&self) -> Option<&TrafficModel> {
+        self.inner.traffic_model()
+    }
+
+    fn geocoder(&self) -> Option<&Geocoder> {
+        self.inner.geocoder()
+    }
+
+    async fn get_node(&self, id: i64) -> Result<Option<RawNode>, io::Error> {
+        if let Some(node) = self.virtual_nodes.get(&id) {
+            return Ok(Some(node.clone()));
+        }
+        Ok(self.inner.get_node(id).await?.map(|node| self.patch(node)))
+    }
+
+    async fn get_neighbors(&self, node_ids: &[i64]) -> Result<Vec<RawNode>, io::Error> {
+        let real_ids: Vec<i64> = node_ids.iter().copied().filter(|id| !is_virtual(*id)).collect();
+        let mut nodes: Vec<RawNode> = self
+            .inner
+            .get_neighbors(&real_ids)
+            .await?
+            .into_iter()
+            .map(|node| self.patch(node))
+            .collect();
+        nodes.extend(node_ids.iter().filter_map(|id| self.virtual_nodes.get(id).cloned()));
+        Ok(nodes)
+    }
+
+    async fn nearest_node(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Result<Option<RawNode>, io::Error> {
+        self.inner.nearest_node(latitude, longitude, mode).await
+    }
+
+    async fn nearest_edge(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Result<Option<EdgeSnap>, io::Error> {
+        self.inner.nearest_edge(latitude, longitude, mode).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra;
+    use crate::graph::Graph;
+    use crate::test_support;
+
+    // A 111 m street running north from 1 to 2 with a stored length of 120 m, one-way
+    // for cars, two-way for bikes
+    fn street() -> Graph {
+        let node = |id: i64, lat: f64, neighbor: i64, car: Vec<i64>| RawNode {
+            adjacency_lengths: Some(vec![120.0]),
+            car_adjacency_list: Some(car),
+            bike_adjacency_list: Some(vec![neighbor]),
+            ..test_support::node(id, lat, -74.0, vec![neighbor])
+        };
+        Graph::from_nodes(vec![node(1, 40.0, 2, vec![2]), node(2, 40.001, 1, vec![])])
+    }
+
+    #[tokio::test]
+    async fn mid_edge_snap_splits_the_edge() {
+        let graph = street();
+        let (from, to) = (graph.get_node(1).unwrap(), graph.get_node(2).unwrap());
+        // 40 % of the way up, 8.5 m east of the street
+        let snap = nearest_edge(TravelMode::Car, [(from, to)].into_iter(), |_, _| None, 40.0004, -73.9999).unwrap();
+        assert!((snap.distance - 8.5).abs() < 0.1, "{}", snap.distance);
+        assert_eq!(snap.snapped_point().edge, Some((1, 2)));
+
+        let overlay = SnapOverlay::new(&graph, &[snap]);
+        let virtual_node = overlay.endpoint(0);
+        assert!(is_virtual(virtual_node.id));
+        assert_eq!(virtual_node.adjacency_list, [1, 2]);
+
+        // The two halves add up to the stored length, split where the point projects
+        let start = overlay.get_node(1).await.unwrap().unwrap();
+        let end = overlay.get_node(2).await.unwrap().unwrap();
+        for mode in [TravelMode::Foot, TravelMode::Bike] {
+            let before = edge_weight(mode, &start, &virtual_node);
+            let after = edge_weight(mode, &virtual_node, &end);
+            assert!((before - 48.0).abs() < 0.1, "{:?} {}", mode, before);
+            assert!((before + after - 120.0).abs() < 1e-9, "{:?} {} + {}", mode, before, after);
+            assert!((edge_weight(mode, &end, &virtual_node) - after).abs() < 1e-9);
+        }
+        let before = edge_weight(TravelMode::Car, &start, &virtual_node);
+        let after = edge_weight(TravelMode::Car, &virtual_node, &end);
+        assert!((before + after - 120.0).abs() < 1e-9);
+
+        // The halves carry the shape on to the snapped point
+        let geometry = overlay.edge_geometry(TravelMode::Foot, 1, virtual_node.id).unwrap();
+        assert_eq!(geometry, [[-74.0, 40.0], [virtual_node.lon, virtual_node.lat]]);
+    }
+
+    #[tokio::test]
+    async fn directed_modes_keep_the_edge_direction() {
+        let graph = street();
+        let (from, to) = (graph.get_node(1).unwrap(), graph.get_node(2).unwrap());
+        let snap = nearest_edge(TravelMode::Car, [(from, to)].into_iter(), |_, _| None, 40.0004, -74.0).unwrap();
+        let overlay = SnapOverlay::new(&graph, &[snap]);
+        let virtual_node = overlay.endpoint(0);
+        let start = overlay.get_node(1).await.unwrap().unwrap();
+        let end = overlay.get_node(2).await.unwrap().unwrap();
+
+        // Cars only continue north: 1 -> virtual -> 2
+        assert_eq!(virtual_node.adjacency(TravelMode::Car), [2]);
+        assert_eq!(virtual_node.reverse_adjacency(TravelMode::Car), [1]);
+        assert!(start.adjacency(TravelMode::Car).contains(&virtual_node.id));
+        assert!(!end.adjacency(TravelMode::Car).contains(&virtual_node.id));
+        // Bikes and pedestrians go both ways
+        assert_eq!(virtual_node.adjacency(TravelMode::Bike), [2, 1]);
+        assert!(end.adjacency(TravelMode::Bike).contains(&virtual_node.id));
+        assert!(end.adjacency(TravelMode::Foot).contains(&virtual_node.id));
+
+        let north = dijkstra::dijkstra(&overlay, TravelMode::Car, virtual_node.clone(), end.clone()).await;
+        assert_eq!(north.node_ids, [virtual_node.id, 2]);
+        let south = dijkstra::dijkstra(&overlay, TravelMode::Car, virtual_node.clone(), start.clone()).await;
+        assert!(south.node_ids.is_empty());
+        let south = dijkstra::dijkstra(&overlay, TravelMode::Bike, virtual_node.clone(), start).await;
+        assert_eq!(south.node_ids, [virtual_node.id, 1]);
+    }
+}
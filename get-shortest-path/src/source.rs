@@ -0,0 +1,566 @@
+use crate::alt::{self, LandmarkTables};
+use crate::ch::{self, ContractionHierarchy};
+use crate::database::{self, AdjacencyColumns, RawNode};
+use crate::geocode::{self, Geocoder};
+use crate::graph::Graph;
+use crate::mode::TravelMode;
+use crate::snap::{self, EdgeSnap, METERS_PER_DEGREE_LATITUDE};
+use crate::traffic::{self, TrafficModel};
+use crate::ways::{self, WayTags};
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::io;
+
+use lambda_runtime::tracing::info;
+
+// Number of frontier nodes whose unseen neighbors are fetched in a single query
+const POSTGRES_FRONTIER_BATCH_SIZE: usize = 8;
+
+// Half-width of the box searched around a coordinate when snapping, doubled up to
+// the maximum until it holds something routable
+const SNAP_BOX_METERS: f64 = 250.0;
+const MAX_SNAP_BOX_METERS: f64 = 4000.0;
+
+/// Storage backend a search runs against.
+///
+/// Every search talks to the graph only through this trait, so the same code runs over
+/// Postgres, a preloaded graph or the CSV export. Backends must implement node lookup
+/// (`get_node`, `get_neighbors`) and snapping (`nearest_node`, `nearest_edge`). The rest
+/// have defaults for what a backend may lack: in-memory node borrowing, edge shapes and
+/// way tags, and the optional preprocessed data (contraction hierarchies, landmark
+/// tables, traffic, place names) that some requests need.
+pub trait GraphSource: Sync {
+    /// How many frontier nodes get their neighbors fetched together. Only worth
+    /// raising above 1 when every fetch is a round-trip.
+    fn frontier_batch_size(&self) -> usize {
+        1
+    }
+
+    /// Whether the backend holds every node in memory, so searches borrow nodes through
+    /// `borrow_node` instead of fetching and caching copies.
+    fn is_resident(&self) -> bool {
+        false
+    }
+
+    /// A node straight from memory, only answered when `is_resident`.
+    fn borrow_node(&self, _id: i64) -> Option<Cow<'_, RawNode>> {
+        None
+    }
+
+    /// Look up a single node by OSM id.
+    fn get_node(&self, id: i64) -> impl Future<Output = Result<Option<RawNode>, io::Error>> + Send;
+
+    /// Neighbor expansion: fetch the nodes behind a set of adjacency ids.
+    /// Ids the backend has no row for are left out of the result.
+    fn get_neighbors(&self, node_ids: &[i64]) -> impl Future<Output = Result<Vec<RawNode>, io::Error>> + Send;
+
+    /// Shape of the edge `mode` travels from `from_id` to `to_id`, both endpoints included,
+    /// when the backend stores more than the two node coordinates.
+    ///
+    /// Only the CSV backend has shapes: its edges span several way nodes. adjacent_nodes
+    /// links consecutive way nodes, so Postgres and memory edges are straight segments
+    /// and a path through their nodes already follows the street.
+    fn edge_geometry(&self, _mode: TravelMode, _from_id: i64, _to_id: i64) -> Option<Vec<[f64; 2]>> {
+        None
+    }
+
+    /// Name and highway class of the way the edge `mode` travels from `from_id` to `to_id`
+    /// belongs to, if way tags were loaded.
+    fn edge_way(&self, _mode: TravelMode, _from_id: i64, _to_id: i64) -> Option<&WayTags> {
+        None
+    }
+
+    /// Preprocessed contraction hierarchy for `mode`, if one was loaded.
+    fn contraction_hierarchy(&self, _mode: TravelMode) -> Option<&ContractionHierarchy> {
+        None
+    }
+
+    /// Landmark distance tables for `mode`, if they were loaded.
+    fn landmark_tables(&self, _mode: TravelMode) -> Option<&LandmarkTables> {
+        None
+    }
+
+    /// Hour-of-day congestion model for departure-time routing, if traffic data was loaded.
+    fn traffic_model(&self) -> Option<&TrafficModel> {
+        None
+    }
+
+    /// Place names waypoints may be given by, if any were loaded.
+    fn geocoder(&self) -> Option<&Geocoder> {
+        None
+    }
+
+    /// Snap a coordinate to the nearest node `mode` can leave from.
+    fn nearest_node(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        mode: TravelMode,
+    ) -> impl Future<Output = Result<Option<RawNode>, io::Error>> + Send;
+
+    /// Project a coordinate onto the nearest edge `mode` may travel along.
+    fn nearest_edge(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        mode: TravelMode,
+    ) -> impl Future<Output = Result<Option<EdgeSnap>, io::Error>> + Send;
+}
+
+/// Queries planet_osm_nodes + adjacent_nodes on demand.
+/// Edges join consecutive way nodes, so no edge geometry is needed.
+pub struct PostgresSource {
+    pool: sqlx::PgPool,
+    columns: AdjacencyColumns,
+    // Small enough to keep in memory even when the graph is not
+    traffic: Option<TrafficModel>,
+    geocoder: Option<Geocoder>,
+}
+
+impl PostgresSource {
+    // Checks once which adjacency columns the tables have
+    pub async fn new(pool: sqlx::PgPool) -> Result<PostgresSource, io::Error> {
+        let columns = AdjacencyColumns::detect(&pool).await?;
+        Ok(PostgresSource {
+            pool,
+            columns,
+            traffic: None,
+            geocoder: None,
+        })
+    }
+
+    pub fn set_traffic(&mut self, traffic: TrafficModel) {
+        self.traffic = Some(traffic);
+    }
+
+    pub fn set_geocoder(&mut self, geocoder: Geocoder) {
+        self.geocoder = Some(geocoder);
+    }
+
+    pub fn pool(&self) -> &sqlx::PgPool {
+        &self.pool
+    }
+
+    // Routable nodes within `half_width` meters north, south, east and west of a coordinate
+    async fn nodes_around(&self, latitude: f64, longitude: f64, half_width: f64) -> Result<Vec<RawNode>, io::Error> {
+        let lat_delta = half_width / METERS_PER_DEGREE_LATITUDE;
+        let lon_delta = lat_delta / latitude.to_radians().cos().max(0.01);
+        database::get_nodes_in_box(
+            &self.pool,
+            self.columns,
+            latitude - lat_delta,
+            longitude - lon_delta,
+            latitude + lat_delta,
+            longitude + lon_delta,
+        )
+        .await
+    }
+}
+
+impl GraphSource for PostgresSource {
+    fn frontier_batch_size(&self) -> usize {
+        POSTGRES_FRONTIER_BATCH_SIZE
+    }
+
+    fn traffic_model(&self) -> Option<&TrafficModel> {
+        self.traffic.as_ref()
+    }
+
+    fn geocoder(&self) -> Option<&Geocoder> {
+        self.geocoder.as_ref()
+    }
+
+    async fn get_node(&self, id: i64) -> Result<Option<RawNode>, io::Error> {
+        let nodes = database::get_nodes_by_ids(&self.pool, self.columns, &[id]).await?;
+        Ok(nodes.into_iter().next())
+    }
+
+    async fn get_neighbors(&self, node_ids: &[i64]) -> Result<Vec<RawNode>, io::Error> {
+        database::get_nodes_by_ids(&self.pool, self.columns, node_ids).await
+    }
+
+    // Grow the box until its closest node is nearer than anything outside could be
+    async fn nearest_node(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Result<Option<RawNode>, io::Error> {
+        let mut half_width = SNAP_BOX_METERS;
+        loop {
+            let nearest = self
+                .nodes_around(latitude, longitude, half_width)
+                .await?
+                .into_iter()
+                .filter(|node| !node.adjacency(mode).is_empty())
+                .map(|node| (snap::local_distance(latitude, longitude, node.lat, node.lon), node))
+                .min_by(|(a, _), (b, _)| a.total_cmp(b));
+            match nearest {
+                Some((distance, node)) if distance <= half_width => return Ok(Some(node)),
+                nearest if half_width >= MAX_SNAP_BOX_METERS => return Ok(nearest.map(|(_, node)| node)),
+                _ => half_width *= 2.0,
+            }
+        }
+    }
+
+    // Edges leaving the box are included by fetching their far ends too. An edge
+    // passing close by with both ends outside the box can still be missed.
+    async fn nearest_edge(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Result<Option<EdgeSnap>, io::Error> {
+        let mut half_width = SNAP_BOX_METERS;
+        loop {
+            let mut nodes: HashMap<i64, RawNode> = self
+                .nodes_around(latitude, longitude, half_width)
+                .await?
+                .into_iter()
+                .map(|node| (node.id, node))
+                .collect();
+            let mut outside_ids: Vec<i64> = nodes
+                .values()
+                .flat_map(|node| node.adjacency(mode).iter().copied())
+                .filter(|id| !nodes.contains_key(id))
+                .collect();
+            outside_ids.sort_unstable();
+            outside_ids.dedup();
+            for node in database::get_nodes_by_ids(&self.pool, self.columns, &outside_ids).await? {
+                nodes.insert(node.id, node);
+            }
+
+            let edges = nodes.values().flat_map(|node| {
+                node.adjacency(mode)
+                    .iter()
+                    .filter_map(|id| nodes.get(id))
+                    .map(move |next| (node, next))
+            });
+            let nearest = snap::nearest_edge(mode, edges, |_, _| None, latitude, longitude);
+            match nearest {
+                Some(edge) if edge.distance <= half_width => return Ok(Some(edge)),
+                nearest if half_width >= MAX_SNAP_BOX_METERS => return Ok(nearest),
+                _ => half_width *= 2.0,
+            }
+        }
+    }
+}
+
+impl GraphSource for Graph {
+    fn is_resident(&self) -> bool {
+        true
+    }
+
+    fn borrow_node(&self, id: i64) -> Option<Cow<'_, RawNode>> {
+        Graph::get_node(self, id).map(Cow::Borrowed)
+    }
+
+    fn edge_geometry(&self, mode: TravelMode, from_id: i64, to_id: i64) -> Option<Vec<[f64; 2]>> {
+        Graph::edge_geometry(self, mode, from_id, to_id)
+    }
+
+    fn edge_way(&self, mode: TravelMode, from_id: i64, to_id: i64) -> Option<&WayTags> {
+        Graph::edge_way(self, mode, from_id, to_id)
+    }
+
+    fn contraction_hierarchy(&self, mode: TravelMode) -> Option<&ContractionHierarchy> {
+        self.hierarchy(mode)
+    }
+
+    fn landmark_tables(&self, mode: TravelMode) -> Option<&LandmarkTables> {
+        self.landmarks(mode)
+    }
+
+    fn traffic_model(&self) -> Option<&TrafficModel> {
+        self.traffic()
+    }
+
+    fn geocoder(&self) -> Option<&Geocoder> {
+        Graph::geocoder(self)
+    }
+
+    async fn get_node(&self, id: i64) -> Result<Option<RawNode>, io::Error> {
+        Ok(Graph::get_node(self, id).cloned())
+    }
+
+    async fn get_neighbors(&self, node_ids: &[i64]) -> Result<Vec<RawNode>, io::Error> {
+        Ok(node_ids
+            .iter()
+            .filter_map(|id| Graph::get_node(self, *id).cloned())
+            .collect())
+    }
+
+    async fn nearest_node(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Result<Option<RawNode>, io::Error> {
+        Ok(Graph::nearest_node(self, latitude, longitude, mode).cloned())
+    }
+
+    async fn nearest_edge(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Result<Option<EdgeSnap>, io::Error> {
+        Ok(Graph::nearest_edge(self, latitude, longitude, mode))
+    }
+}
+
+/// Backend picked at startup from `GRAPH_BACKEND`.
+pub enum Backend {
+    /// Per-node queries, for regions too large to preload.
+    Postgres(PostgresSource),
+    /// Graph preloaded from Postgres once per container.
+    Memory(Graph),
+    /// Graph loaded from nodes.csv / edges.csv, no database needed.
+    Csv(Graph),
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Postgres(_) => "postgres",
+            Backend::Memory(_) => "memory",
+            Backend::Csv(_) => "csv",
+        }
+    }
+}
+
+impl GraphSource for Backend {
+    fn frontier_batch_size(&self) -> usize {
+        match self {
+            Backend::Postgres(source) => source.frontier_batch_size(),
+            Backend::Memory(graph) | Backend::Csv(graph) => graph.frontier_batch_size(),
+        }
+    }
+
+    fn is_resident(&self) -> bool {
+        match self {
+            Backend::Postgres(source) => source.is_resident(),
+            Backend::Memory(graph) | Backend::Csv(graph) => graph.is_resident(),
+        }
+    }
+
+    fn borrow_node(&self, id: i64) -> Option<Cow<'_, RawNode>> {
+        match self {
+            Backend::Postgres(source) => source.borrow_node(id),
+            Backend::Memory(graph) | Backend::Csv(graph) => GraphSource::borrow_node(graph, id),
+        }
+    }
+
+    fn edge_geometry(&self, mode: TravelMode, from_id: i64, to_id: i64) -> Option<Vec<[f64; 2]>> {
+        match self {
+            Backend::Postgres(source) => source.edge_geometry(mode, from_id, to_id),
+            Backend::Memory(graph) | Backend::Csv(graph) => GraphSource::edge_geometry(graph, mode, from_id, to_id),
+        }
+    }
+
+    fn edge_way(&self, mode: TravelMode, from_id: i64, to_id: i64) -> Option<&WayTags> {
+        match self {
+            Backend::Postgres(source) => source.edge_way(mode, from_id, to_id),
+            Backend::Memory(graph) | Backend::Csv(graph) => graph.edge_way(mode, from_id, to_id),
+        }
+    }
+
+    fn contraction_hierarchy(&self, mode: TravelMode) -> Option<&ContractionHierarchy> {
+        match self {
+            Backend::Postgres(source) => source.contraction_hierarchy(mode),
+            Backend::Memory(graph) | Backend::Csv(graph) => graph.hierarchy(mode),
+        }
+    }
+
+    fn landmark_tables(&self, mode: TravelMode) -> Option<&LandmarkTables> {
+        match self {
+            Backend::Postgres(source) => source.landmark_tables(mode),
+            Backend::Memory(graph) | Backend::Csv(graph) => graph.landmarks(mode),
+        }
+    }
+
+    fn traffic_model(&self) -> Option<&TrafficModel> {
+        match self {
+            Backend::Postgres(source) => source.traffic_model(),
+            Backend::Memory(graph) | Backend::Csv(graph) => graph.traffic(),
+        }
+    }
+
+    fn geocoder(&self) -> Option<&Geocoder> {
+        match self {
+            Backend::Postgres(source) => source.geocoder(),
+            Backend::Memory(graph) | Backend::Csv(graph) => graph.geocoder(),
+        }
+    }
+
+    async fn get_node(&self, id: i64) -> Result<Option<RawNode>, io::Error> {
+        match self {
+            Backend::Postgres(source) => source.get_node(id).await,
+            Backend::Memory(graph) | Backend::Csv(graph) => GraphSource::get_node(graph, id).await,
+        }
+    }
+
+    async fn get_neighbors(&self, node_ids: &[i64]) -> Result<Vec<RawNode>, io::Error> {
+        match self {
+            Backend::Postgres(source) => source.get_neighbors(node_ids).await,
+            Backend::Memory(graph) | Backend::Csv(graph) => graph.get_neighbors(node_ids).await,
+        }
+    }
+
+    async fn nearest_node(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Result<Option<RawNode>, io::Error> {
+        match self {
+            Backend::Postgres(source) => source.nearest_node(latitude, longitude, mode).await,
+            Backend::Memory(graph) | Backend::Csv(graph) => {
+                GraphSource::nearest_node(graph, latitude, longitude, mode).await
+            }
+        }
+    }
+
+    async fn nearest_edge(&self, latitude: f64, longitude: f64, mode: TravelMode) -> Result<Option<EdgeSnap>, io::Error> {
+        match self {
+            Backend::Postgres(source) => source.nearest_edge(latitude, longitude, mode).await,
+            Backend::Memory(graph) | Backend::Csv(graph) => {
+                GraphSource::nearest_edge(graph, latitude, longitude, mode).await
+            }
+        }
+    }
+}
+
+fn database_url() -> Result<String, io::Error> {
+    env::var("DATABASE_URL")
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DATABASE_URL must be set"))
+}
+
+// Capacity of a car edge from the highway class of its way, once way tags are loaded
+fn edge_capacity(graph: &Graph, from_id: i64, to_id: i64) -> f64 {
+    traffic::road_capacity(graph.edge_way(TravelMode::Car, from_id, to_id).and_then(|way| way.highway.as_deref()))
+}
+
+// Traffic is optional: without edge_traffic every departure time gets free-flow speeds
+async fn load_traffic(pool: &sqlx::PgPool, capacity: impl Fn(i64, i64) -> f64) -> Option<TrafficModel> {
+    match database::get_edge_traffic(pool).await {
+        Ok(volumes) => {
+            let traffic = TrafficModel::from_volumes(volumes, capacity);
+            info!("Loaded traffic volumes for {} edges", traffic.edge_count());
+            Some(traffic)
+        }
+        Err(err) => {
+            info!("No edge traffic loaded, using free-flow speeds: {}", err);
+            None
+        }
+    }
+}
+
+// Place names are optional too, routes by coordinates work without them
+async fn load_geocoder(pool: &sqlx::PgPool) -> Option<Geocoder> {
+    match database::get_places(pool).await {
+        Ok(places) => {
+            let geocoder = Geocoder::new(places);
+            info!("Loaded {} named places", geocoder.place_count());
+            Some(geocoder)
+        }
+        Err(err) => {
+            info!("No place names loaded, waypoints need coordinates: {}", err);
+            None
+        }
+    }
+}
+
+// Build the backend named by GRAPH_BACKEND (postgres, memory or csv, defaults to memory).
+// The csv backend reads NODES_CSV / EDGES_CSV, defaulting to nodes.csv / edges.csv.
+// In-memory backends also load the contraction hierarchies at CH_PATH and the ALT
+// landmark tables at ALT_PATH when those are set. Traffic volumes come from the
+// edge_traffic table, or from TRAFFIC_CSV for the csv backend. Way names and highway
+// classes come from planet_osm_ways, or from the output_ways.txt dump at WAYS_TXT. Place
+// names come from planet_osm_point / planet_osm_polygon, or from the output_nodes.txt
+// dump at PLACES_TXT.
+pub async fn load_source() -> Result<Backend, io::Error> {
+    let mut backend = load_backend().await?;
+
+    if let Ok(ch_path) = env::var("CH_PATH") {
+        match &mut backend {
+            Backend::Memory(graph) | Backend::Csv(graph) => {
+                for hierarchy in ch::read_hierarchies(&ch_path)? {
+                    info!(
+                        "Loaded {:?} contraction hierarchy with {} nodes and {} arcs",
+                        hierarchy.mode(),
+                        hierarchy.node_count(),
+                        hierarchy.arc_count()
+                    );
+                    graph.set_hierarchy(hierarchy);
+                }
+            }
+            Backend::Postgres(_) => info!("CH_PATH ignored, the postgres backend has no in-memory graph"),
+        }
+    }
+
+    if let Ok(alt_path) = env::var("ALT_PATH") {
+        match &mut backend {
+            Backend::Memory(graph) | Backend::Csv(graph) => {
+                for tables in alt::read_landmarks(&alt_path)? {
+                    info!(
+                        "Loaded {:?} landmark tables with {} landmarks ({} bytes)",
+                        tables.mode(),
+                        tables.landmarks().len(),
+                        tables.table_bytes()
+                    );
+                    graph.set_landmarks(tables);
+                }
+            }
+            Backend::Postgres(_) => info!("ALT_PATH ignored, the postgres backend has no in-memory graph"),
+        }
+    }
+
+    info!("Using {} graph backend", backend.name());
+    Ok(backend)
+}
+
+// The backend without the CH_PATH / ALT_PATH tables, for the offline builders that are
+// about to write those files and only need the graph itself
+pub async fn load_backend() -> Result<Backend, io::Error> {
+    let backend_name = env::var("GRAPH_BACKEND").unwrap_or_else(|_| "memory".to_string());
+
+    let backend = match backend_name.as_str() {
+        "postgres" => {
+            let pool = database::create_pool(&database_url()?).await?;
+            let mut source = PostgresSource::new(pool).await?;
+            // Without way tags every street gets the default capacity
+            if let Some(traffic) = load_traffic(source.pool(), |_, _| traffic::road_capacity(None)).await {
+                source.set_traffic(traffic);
+            }
+            if let Some(geocoder) = load_geocoder(source.pool()).await {
+                source.set_geocoder(geocoder);
+            }
+            Backend::Postgres(source)
+        }
+        "memory" => {
+            let pool = database::create_pool(&database_url()?).await?;
+            let mut graph = Graph::load(&pool).await?;
+            match database::get_ways(&pool).await {
+                Ok(ways) => {
+                    graph.set_ways(&ways);
+                    info!("Loaded tags of {} ways", graph.way_count());
+                }
+                Err(err) => info!("No way tags loaded: {}", err),
+            }
+            if let Some(traffic) = load_traffic(&pool, |from_id, to_id| edge_capacity(&graph, from_id, to_id)).await {
+                graph.set_traffic(traffic);
+            }
+            if let Some(geocoder) = load_geocoder(&pool).await {
+                graph.set_geocoder(geocoder);
+            }
+            Backend::Memory(graph)
+        }
+        "csv" => {
+            let nodes_path = env::var("NODES_CSV").unwrap_or_else(|_| "nodes.csv".to_string());
+            let edges_path = env::var("EDGES_CSV").unwrap_or_else(|_| "edges.csv".to_string());
+            let mut graph = Graph::from_csv(&nodes_path, &edges_path)?;
+            if let Ok(ways_path) = env::var("WAYS_TXT") {
+                graph.set_ways(&ways::read_ways_txt(&ways_path)?);
+                info!("Loaded tags of {} ways from {}", graph.way_count(), ways_path);
+            }
+            if let Ok(traffic_path) = env::var("TRAFFIC_CSV") {
+                let traffic = TrafficModel::from_csv(&traffic_path, |from_id, to_id| edge_capacity(&graph, from_id, to_id))?;
+                info!("Loaded traffic volumes for {} edges from {}", traffic.edge_count(), traffic_path);
+                graph.set_traffic(traffic);
+            }
+            if let Ok(places_path) = env::var("PLACES_TXT") {
+                let geocoder = Geocoder::new(geocode::read_places_txt(&places_path)?);
+                info!("Loaded {} named places from {}", geocoder.place_count(), places_path);
+                graph.set_geocoder(geocoder);
+            }
+            Backend::Csv(graph)
+        }
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown GRAPH_BACKEND '{}', expected postgres, memory or csv", other),
+            ));
+        }
+    };
+
+    Ok(backend)
+}
@@ -0,0 +1,303 @@
+// In-memory spatial index over the routing graph, so snapping never needs a database.
+// A uniform lat / lon grid with cells about CELL_METERS on a side: nodes go in the cell
+// they fall in, edges in every cell their bounding box touches. Lookups walk rings of
+// cells outward from the query point and stop once no farther ring can hold anything
+// closer than what was already found.
+
+use crate::database::RawNode;
+use crate::snap::{local_distance, METERS_PER_DEGREE_LATITUDE};
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+// A dense downtown cell holds a few dozen nodes, a suburban one a handful
+const CELL_METERS: f64 = 50.0;
+
+type Cell = (i32, i32);
+
+#[derive(Debug, Default)]
+struct Grid<T> {
+    cells: HashMap<Cell, Vec<T>>,
+    // Occupied extent, rings beyond it are never visited
+    min: Cell,
+    max: Cell,
+}
+
+impl<T: Clone> Grid<T> {
+    fn insert(&mut self, from: Cell, to: Cell, item: T) {
+        if self.cells.is_empty() {
+            (self.min, self.max) = (from, to);
+        }
+        self.min = (self.min.0.min(from.0), self.min.1.min(from.1));
+        self.max = (self.max.0.max(to.0), self.max.1.max(to.1));
+        for row in from.0..=to.0 {
+            for column in from.1..=to.1 {
+                self.cells.entry((row, column)).or_default().push(item.clone());
+            }
+        }
+    }
+
+    // Rings around `center` that touch the occupied extent, closest first
+    fn rings(&self, center: Cell) -> Range<i32> {
+        if self.cells.is_empty() {
+            return 0..0;
+        }
+        let outside = [self.min.0 - center.0, center.0 - self.max.0, self.min.1 - center.1, center.1 - self.max.1];
+        let reach = [center.0 - self.min.0, self.max.0 - center.0, center.1 - self.min.1, self.max.1 - center.1];
+        outside.into_iter().max().unwrap().max(0)..reach.into_iter().max().unwrap() + 1
+    }
+
+    // Items in the cells exactly `ring` cells away from `center`, clipped to the extent
+    fn ring(&self, center: Cell, ring: i32) -> impl Iterator<Item = &T> {
+        let (top, bottom) = (center.0 - ring, center.0 + ring);
+        let (left, right) = (center.1 - ring, center.1 + ring);
+        let columns = left.max(self.min.1)..=right.min(self.max.1);
+        let side_rows = (top + 1).max(self.min.0)..=(bottom - 1).min(self.max.0);
+
+        let rows = if ring == 0 { vec![top] } else { vec![top, bottom] };
+        let mut cells: Vec<Cell> = rows
+            .into_iter()
+            .filter(|row| (self.min.0..=self.max.0).contains(row))
+            .flat_map(|row| columns.clone().map(move |column| (row, column)))
+            .collect();
+        for column in [left, right] {
+            if ring > 0 && (self.min.1..=self.max.1).contains(&column) {
+                cells.extend(side_rows.clone().map(|row| (row, column)));
+            }
+        }
+        cells.into_iter().filter_map(|cell| self.cells.get(&cell)).flatten()
+    }
+}
+
+/// Grid index over the nodes and edges of a graph, built once at load time.
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    // Cell size in degrees, square on the ground at the graph's mean latitude
+    cell_lat: f64,
+    cell_lon: f64,
+    nodes: Grid<(i64, f64, f64)>,
+    // Undirected, smaller id first
+    edges: Grid<(i64, i64)>,
+}
+
+impl SpatialIndex {
+    /// Index every node with at least one neighbor and every edge between them.
+    /// `geometry` returns the stored shape of an edge, if any.
+    pub fn build<'a>(
+        nodes: impl IntoIterator<Item = &'a RawNode>,
+        geometry: impl Fn(i64, i64) -> Option<Vec<[f64; 2]>>,
+    ) -> SpatialIndex {
+        let nodes: HashMap<i64, &RawNode> = nodes.into_iter().map(|node| (node.id, node)).collect();
+        let mean_lat = nodes.values().map(|node| node.lat).sum::<f64>() / nodes.len().max(1) as f64;
+        let cell_lat = CELL_METERS / METERS_PER_DEGREE_LATITUDE;
+        let mut index = SpatialIndex {
+            cell_lat,
+            cell_lon: cell_lat / mean_lat.to_radians().cos().max(0.01),
+            ..SpatialIndex::default()
+        };
+
+        let mut seen: HashSet<(i64, i64)> = HashSet::new();
+        for node in nodes.values() {
+            let neighbor_ids = node
+                .adjacency_list
+                .iter()
+                .chain(node.car_adjacency_list.iter().flatten())
+                .chain(node.bike_adjacency_list.iter().flatten());
+            let mut routable = false;
+            for neighbor_id in neighbor_ids {
+                let Some(neighbor) = nodes.get(neighbor_id) else { continue };
+                routable = true;
+                let key = (node.id.min(neighbor.id), node.id.max(neighbor.id));
+                if !seen.insert(key) {
+                    continue;
+                }
+                let shape = geometry(key.0, key.1).unwrap_or_else(|| vec![[node.lon, node.lat], [neighbor.lon, neighbor.lat]]);
+                let (mut min_lat, mut min_lon, mut max_lat, mut max_lon) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+                for [lon, lat] in shape {
+                    (min_lat, max_lat) = (min_lat.min(lat), max_lat.max(lat));
+                    (min_lon, max_lon) = (min_lon.min(lon), max_lon.max(lon));
+                }
+                let (from, to) = (index.cell(min_lat, min_lon), index.cell(max_lat, max_lon));
+                index.edges.insert(from, to, key);
+            }
+            if routable {
+                let cell = index.cell(node.lat, node.lon);
+                index.nodes.insert(cell, cell, (node.id, node.lat, node.lon));
+            }
+        }
+        index
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.cells.values().map(Vec::len).sum()
+    }
+
+    fn cell(&self, latitude: f64, longitude: f64) -> Cell {
+        ((latitude / self.cell_lat).floor() as i32, (longitude / self.cell_lon).floor() as i32)
+    }
+
+    // Meters from a point to the nearest cell `ring` rings away from its own, a lower
+    // bound on the distance to anything stored in that ring
+    fn ring_distance(&self, ring: i32, latitude: f64, longitude: f64) -> f64 {
+        if ring == 0 {
+            return 0.0;
+        }
+        let row = latitude / self.cell_lat;
+        let column = longitude / self.cell_lon;
+        let (row_offset, column_offset) = (row - row.floor(), column - column.floor());
+        let cell_height = self.cell_lat * METERS_PER_DEGREE_LATITUDE;
+        let cell_width = self.cell_lon * latitude.to_radians().cos() * METERS_PER_DEGREE_LATITUDE;
+        let rings_between = (ring - 1) as f64;
+        [
+            (rings_between + row_offset) * cell_height,
+            (rings_between + 1.0 - row_offset) * cell_height,
+            (rings_between + column_offset) * cell_width,
+            (rings_between + 1.0 - column_offset) * cell_width,
+        ]
+        .into_iter()
+        .fold(f64::INFINITY, f64::min)
+    }
+
+    /// The `k` closest indexed nodes `filter` accepts, nearest first, with meters to each.
+    pub fn nearest(&self, latitude: f64, longitude: f64, k: usize, filter: impl Fn(i64) -> bool) -> Vec<(i64, f64)> {
+        let center = self.cell(latitude, longitude);
+        let mut found: Vec<(i64, f64)> = Vec::new();
+        if k == 0 {
+            return found;
+        }
+        for ring in self.nodes.rings(center) {
+            if found.len() >= k && found[k - 1].1 <= self.ring_distance(ring, latitude, longitude) {
+                break;
+            }
+            for (id, lat, lon) in self.nodes.ring(center, ring) {
+                if filter(*id) {
+                    found.push((*id, local_distance(latitude, longitude, *lat, *lon)));
+                }
+            }
+            found.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+            found.truncate(k);
+        }
+        found
+    }
+
+    /// Every indexed node `filter` accepts within `radius` meters, nearest first.
+    pub fn within(&self, latitude: f64, longitude: f64, radius: f64, filter: impl Fn(i64) -> bool) -> Vec<(i64, f64)> {
+        let center = self.cell(latitude, longitude);
+        let mut found: Vec<(i64, f64)> = Vec::new();
+        for ring in self.nodes.rings(center) {
+            if self.ring_distance(ring, latitude, longitude) > radius {
+                break;
+            }
+            for (id, lat, lon) in self.nodes.ring(center, ring) {
+                let distance = local_distance(latitude, longitude, *lat, *lon);
+                if distance <= radius && filter(*id) {
+                    found.push((*id, distance));
+                }
+            }
+        }
+        found.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        found
+    }
+
+    /// Edges that may pass within `radius` meters, as undirected pairs with the smaller
+    /// id first. The second value is false while edges farther out remain unvisited.
+    pub fn edges_near(&self, latitude: f64, longitude: f64, radius: f64) -> (Vec<(i64, i64)>, bool) {
+        let center = self.cell(latitude, longitude);
+        let mut edges: HashSet<(i64, i64)> = HashSet::new();
+        let mut complete = true;
+        for ring in self.edges.rings(center) {
+            if self.ring_distance(ring, latitude, longitude) > radius {
+                complete = false;
+                break;
+            }
+            edges.extend(self.edges.ring(center, ring).copied());
+        }
+        (edges.into_iter().collect(), complete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::princeton;
+
+    // The k closest nodes by checking every one, what the index has to reproduce
+    fn brute_force(
+        nodes: &[&RawNode],
+        latitude: f64,
+        longitude: f64,
+        k: usize,
+        filter: impl Fn(i64) -> bool,
+    ) -> Vec<(i64, f64)> {
+        let mut found: Vec<(i64, f64)> = nodes
+            .iter()
+            .filter(|node| filter(node.id))
+            .map(|node| (node.id, local_distance(latitude, longitude, node.lat, node.lon)))
+            .collect();
+        found.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        found.truncate(k);
+        found
+    }
+
+    // Compared by distance, nodes at the same spot may come back in either order
+    fn assert_same(found: &[(i64, f64)], expected: &[(i64, f64)], context: &str) {
+        assert_eq!(found.len(), expected.len(), "{}", context);
+        for ((_, found), (_, expected)) in found.iter().zip(expected) {
+            assert!((found - expected).abs() < 1e-6, "{}: {} m instead of {} m", context, found, expected);
+        }
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let graph = princeton();
+        let index = SpatialIndex::build(graph.nodes(), |_, _| None);
+        let routable: Vec<&RawNode> = graph.nodes().filter(|node| !node.adjacency_list.is_empty()).collect();
+        assert_eq!(index.node_count(), routable.len());
+
+        let extent = |values: Vec<f64>| values.iter().fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+        let (min_lat, max_lat) = extent(routable.iter().map(|node| node.lat).collect());
+        let (min_lon, max_lon) = extent(routable.iter().map(|node| node.lon).collect());
+
+        // Points over the bounding box and a margin around it half its size on every side,
+        // most of them in cells without nodes
+        let mut state = 42u64;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let mut queries: Vec<(f64, f64)> = (0..300)
+            .map(|_| {
+                let lat = min_lat + (max_lat - min_lat) * (2.0 * next() - 0.5);
+                let lon = min_lon + (max_lon - min_lon) * (2.0 * next() - 0.5);
+                (lat, lon)
+            })
+            .collect();
+        // Far outside the graph in every direction
+        queries.extend([
+            (min_lat - 0.5, min_lon),
+            (max_lat + 0.5, max_lon),
+            (min_lat, min_lon - 0.5),
+            (max_lat, max_lon + 0.5),
+        ]);
+
+        let mut outside = 0;
+        for (lat, lon) in queries {
+            if !(min_lat..=max_lat).contains(&lat) || !(min_lon..=max_lon).contains(&lon) {
+                outside += 1;
+            }
+            for k in [1, 5] {
+                let expected = brute_force(&routable, lat, lon, k, |_| true);
+                let found = index.nearest(lat, lon, k, |_| true);
+                assert_same(&found, &expected, &format!("{} nearest of ({}, {})", k, lat, lon));
+            }
+            // A filter that rejects most candidates pushes the search farther out
+            let expected = brute_force(&routable, lat, lon, 3, |id| id % 7 == 0);
+            let found = index.nearest(lat, lon, 3, |id| id % 7 == 0);
+            assert_same(&found, &expected, &format!("filtered nearest of ({}, {})", lat, lon));
+        }
+        assert!(outside > 100, "only {} points outside the bounding box", outside);
+
+        assert!(index.nearest(min_lat, min_lon, 0, |_| true).is_empty());
+        assert!(index.nearest(min_lat, min_lon, 3, |_| false).is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+// Street names along a route and under a single point. Every edge of the path is looked
+// up in the way tags of the backend, and consecutive edges with the same name and highway
+// class are merged into one run, so a walk down Nassau Street is one entry however many
+// ways OSM splits it into.
+
+use crate::mode::TravelMode;
+use crate::snap::{local_distance, SnappedPoint};
+use crate::source::GraphSource;
+use crate::ways::WayTags;
+
+use serde::Serialize;
+
+/// A run of consecutive path vertices along one street.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StreetRun {
+    pub name: Option<String>,
+    pub highway: Option<String>,
+    /// Indices into the route path of the first and last vertex of the run.
+    pub from_index: usize,
+    pub to_index: usize,
+    pub distance: f64,
+}
+
+impl StreetRun {
+    // Whether `next` continues this run on the same street
+    fn continues(&self, next: &StreetRun) -> bool {
+        self.to_index == next.from_index && self.name == next.name && self.highway == next.highway
+    }
+}
+
+/// The street a point lies on, found through the edge it snaps onto.
+#[derive(Clone, Debug, Serialize)]
+pub struct Street {
+    /// None when the backend has no tags for that edge.
+    pub way: Option<WayTags>,
+    pub snapped: SnappedPoint,
+}
+
+/// Append `run`, merging it into the last one when it is the same street.
+pub fn push_run(runs: &mut Vec<StreetRun>, run: StreetRun) {
+    match runs.last_mut() {
+        Some(last) if last.continues(&run) => {
+            last.to_index = run.to_index;
+            last.distance += run.distance;
+        }
+        _ => runs.push(run),
+    }
+}
+
+/// Street runs of one search result. `node_ids` is the path as the search returned it and
+/// `path` its coordinates, expanded with the same edge shapes `source` reports.
+pub fn street_runs<S: GraphSource>(source: &S, mode: TravelMode, node_ids: &[i64], path: &[[f64; 2]]) -> Vec<StreetRun> {
+    let mut runs = Vec::new();
+    let Some(last_index) = path.len().checked_sub(1) else {
+        return runs;
+    };
+    let mut index = 0;
+    for pair in node_ids.windows(2) {
+        // Same expansion as path_coordinates: inner shape points plus the next node
+        let points = match source.edge_geometry(mode, pair[0], pair[1]) {
+            Some(geometry) if geometry.len() > 2 => geometry.len() - 1,
+            _ => 1,
+        };
+        let end = (index + points).min(last_index);
+        let distance = path[index..=end]
+            .windows(2)
+            .map(|step| local_distance(step[0][1], step[0][0], step[1][1], step[1][0]))
+            .sum();
+        let way = source.edge_way(mode, pair[0], pair[1]);
+        push_run(
+            &mut runs,
+            StreetRun {
+                name: way.and_then(|way| way.name.clone()),
+                highway: way.and_then(|way| way.highway.clone()),
+                from_index: index,
+                to_index: end,
+                distance,
+            },
+        );
+        index = end;
+    }
+    runs
+}
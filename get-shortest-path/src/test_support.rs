@@ -0,0 +1,42 @@
+// Fixtures shared by the unit tests of the search modules.
+
+use crate::database::RawNode;
+use crate::graph::Graph;
+use crate::mode::TravelMode;
+
+// The Princeton campus extract checked in next to the crate
+pub(crate) fn princeton() -> Graph {
+    let data = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+    Graph::from_csv(&format!("{}/nodes.csv", data), &format!("{}/edges.csv", data)).unwrap()
+}
+
+// `count` pairs spread over the nodes `mode` can leave, most of the others are unreachable.
+// The two strides are primes, so the pairs cover the graph without repeating early.
+pub(crate) fn sample_pairs(graph: &Graph, mode: TravelMode, count: usize) -> Vec<(i64, i64)> {
+    let mut node_ids: Vec<i64> = graph
+        .nodes()
+        .filter(|node| !node.adjacency(mode).is_empty())
+        .map(|node| node.id)
+        .collect();
+    node_ids.sort_unstable();
+    (0..count)
+        .map(|i| (node_ids[(i * 7919) % node_ids.len()], node_ids[(i * 104729 + 17) % node_ids.len()]))
+        .collect()
+}
+
+// Node with only walking neighbors, directed lists and lengths are set by the caller
+pub(crate) fn node(id: i64, lat: f64, lon: f64, neighbors: Vec<i64>) -> RawNode {
+    RawNode {
+        id,
+        lon,
+        lat,
+        adjacency_list: neighbors,
+        adjacency_lengths: None,
+        car_adjacency_list: None,
+        bike_adjacency_list: None,
+        car_adjacency_lengths: None,
+        bike_adjacency_lengths: None,
+        car_reverse_adjacency_list: None,
+        bike_reverse_adjacency_list: None,
+    }
+}
@@ -0,0 +1,250 @@
+use crate::database::RawNode;
+use crate::dijkstra::{edge_weight, explore, path_coordinates, reconstruct_node_ids, SearchResult};
+use crate::mode::TravelMode;
+use crate::source::GraphSource;
+
+use chrono::{DateTime, NaiveDateTime, NaiveTime, Timelike};
+use chrono_tz::America::New_York;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::io;
+
+use lambda_runtime::tracing::info;
+
+const HOURS: usize = 24;
+const SECONDS_PER_HOUR: f64 = 3600.0;
+const SECONDS_PER_DAY: f64 = 24.0 * SECONDS_PER_HOUR;
+
+// BPR (Bureau of Public Roads) delay curve: t = t0 * (1 + ALPHA * (v / c)^BETA)
+const BPR_ALPHA: f64 = 0.15;
+const BPR_BETA: i32 = 4;
+
+// Vehicles per hour one direction of a street carries when its highway class is unknown
+const DEFAULT_CAPACITY: f64 = 1000.0;
+
+/// Hourly traffic volume on one directed graph edge, as stored in `edge_traffic`.
+#[derive(Clone, Debug, Serialize, Deserialize, FromRow)]
+pub struct EdgeVolume {
+    pub source: i64,
+    pub target: i64,
+    pub hour: i32,
+    pub volume: f64,
+}
+
+/// One hourly count from the `traffic_counts` table written by import-traffic.
+/// Coordinates are WGS84 degrees.
+#[derive(Clone, Debug, Deserialize, FromRow)]
+pub struct TrafficCount {
+    pub segment_id: i32,
+    pub year: i32,
+    pub month: i32,
+    pub day: i32,
+    pub hour: i32,
+    pub volume: i32,
+    pub boro: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub street: String,
+    pub from_street: String,
+    pub to_street: String,
+    pub direction: String,
+}
+
+/// Hour-of-day congestion for car travel times.
+///
+/// Each counted edge gets 24 delay factors from its hourly volumes; edges without
+/// counts use the network-wide average factor for that hour.
+#[derive(Debug, Default)]
+pub struct TrafficModel {
+    factors: HashMap<(i64, i64), [f64; HOURS]>,
+    default_factors: [f64; HOURS],
+}
+
+/// Hourly capacity of one direction of a street in vehicles, by OSM highway class.
+/// Counts only give volumes; a fixed capacity per class keeps the factors of a quiet
+/// side street and a busy avenue comparable.
+pub fn road_capacity(highway: Option<&str>) -> f64 {
+    match highway.map(|highway| highway.trim_end_matches("_link")) {
+        Some("motorway") => 4000.0,
+        Some("trunk") => 3000.0,
+        Some("primary") => 1800.0,
+        Some("secondary") => 1400.0,
+        Some("tertiary") => 1000.0,
+        Some("residential" | "unclassified" | "living_street" | "service") => 600.0,
+        _ => DEFAULT_CAPACITY,
+    }
+}
+
+impl TrafficModel {
+    /// Delay factors from hourly edge volumes, `capacity` gives the vehicles per hour
+    /// the edge from source to target carries.
+    pub fn from_volumes(volumes: Vec<EdgeVolume>, capacity: impl Fn(i64, i64) -> f64) -> TrafficModel {
+        // Average repeated counts of the same edge and hour
+        let mut sums: HashMap<(i64, i64), [(f64, usize); HOURS]> = HashMap::new();
+        for volume in volumes {
+            if !(0..HOURS as i32).contains(&volume.hour) || volume.volume < 0.0 {
+                continue;
+            }
+            let slot = &mut sums.entry((volume.source, volume.target)).or_insert([(0.0, 0); HOURS])[volume.hour as usize];
+            slot.0 += volume.volume;
+            slot.1 += 1;
+        }
+
+        let mut factors = HashMap::new();
+        for (edge, hours) in sums {
+            let counted: Vec<f64> = hours.iter().filter(|(_, n)| *n > 0).map(|(sum, n)| sum / *n as f64).collect();
+            if counted.is_empty() {
+                continue;
+            }
+            // Hours without a count get the edge's mean volume
+            let mean = counted.iter().sum::<f64>() / counted.len() as f64;
+            let capacity = capacity(edge.0, edge.1);
+
+            let mut edge_factors = [1.0; HOURS];
+            for (hour, (sum, n)) in hours.iter().enumerate() {
+                let volume = if *n > 0 { sum / *n as f64 } else { mean };
+                edge_factors[hour] = 1.0 + BPR_ALPHA * (volume / capacity).powi(BPR_BETA);
+            }
+            factors.insert(edge, edge_factors);
+        }
+
+        let mut default_factors = [1.0; HOURS];
+        if !factors.is_empty() {
+            for (hour, factor) in default_factors.iter_mut().enumerate() {
+                *factor = factors.values().map(|edge_factors| edge_factors[hour]).sum::<f64>() / factors.len() as f64;
+            }
+        }
+
+        TrafficModel { factors, default_factors }
+    }
+
+    // Read edge_traffic rows exported as CSV (source,target,hour,volume)
+    pub fn from_csv(path: &str, capacity: impl Fn(i64, i64) -> f64) -> Result<TrafficModel, io::Error> {
+        let mut reader = csv::Reader::from_path(path).map_err(io::Error::other)?;
+        let volumes = reader
+            .deserialize()
+            .collect::<Result<Vec<EdgeVolume>, _>>()
+            .map_err(io::Error::other)?;
+        Ok(TrafficModel::from_volumes(volumes, capacity))
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.factors.len()
+    }
+
+    /// Delay factor on free-flow travel time for the edge at `seconds` after midnight,
+    /// interpolated between the hourly values.
+    pub fn factor(&self, from_id: i64, to_id: i64, seconds: f64) -> f64 {
+        let factors = self
+            .factors
+            .get(&(from_id, to_id))
+            .or_else(|| self.factors.get(&(to_id, from_id)))
+            .unwrap_or(&self.default_factors);
+
+        let hours = seconds.rem_euclid(SECONDS_PER_DAY) / SECONDS_PER_HOUR;
+        let hour = hours.floor() as usize % HOURS;
+        let next_hour = (hour + 1) % HOURS;
+        let fraction = hours - hours.floor();
+        factors[hour] * (1.0 - fraction) + factors[next_hour] * fraction
+    }
+}
+
+/// Seconds after midnight in NYC of a departure time given as RFC 3339,
+/// `YYYY-MM-DDTHH:MM[:SS]` or `HH:MM[:SS]`. RFC 3339 times are converted from their
+/// offset, the others are read as local NYC time.
+pub fn parse_departure_time(value: &str) -> Option<f64> {
+    let time = DateTime::parse_from_rfc3339(value)
+        .map(|datetime| datetime.with_timezone(&New_York).time())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").map(|datetime| datetime.time()))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M").map(|datetime| datetime.time()))
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M"))
+        .ok()?;
+    Some(time.num_seconds_from_midnight() as f64)
+}
+
+// Seconds to traverse the edge when entering it at `seconds` after midnight.
+// Only cars are slowed down by traffic.
+pub fn travel_time(from: &RawNode, to: &RawNode, mode: TravelMode, traffic: Option<&TrafficModel>, seconds: f64) -> f64 {
+    let free_flow = edge_weight(mode, from, to) / mode.speed_mps();
+    match (mode, traffic) {
+        (TravelMode::Car, Some(traffic)) => free_flow * traffic.factor(from.id, to.id, seconds),
+        _ => free_flow,
+    }
+}
+
+// Time-dependent Dijkstra: edge costs are travel times evaluated at the moment the
+// edge is entered, `departure` seconds after midnight plus the time spent so far
+pub async fn time_dependent<S: GraphSource>(
+    source: &S,
+    mode: TravelMode,
+    src_node: RawNode,
+    dest_node: RawNode,
+    departure: f64,
+) -> SearchResult {
+    info!("Time-dependent search started for {:?}, departing {:.0}s after midnight", mode, departure);
+    let traffic = source.traffic_model();
+
+    let exploration = explore(
+        source,
+        mode,
+        &src_node,
+        &[dest_node.id],
+        f64::MAX,
+        |_| 0.0,
+        |from, to, elapsed| travel_time(from, to, mode, traffic, departure + elapsed),
+    )
+    .await;
+
+    let end_node_id = if exploration.timed_out { exploration.last_computed_node } else { dest_node.id };
+    let Some(duration) = exploration.distances.get(&end_node_id).copied() else {
+        info!("No path found from node {} to node {}", src_node.id, dest_node.id);
+        return SearchResult {
+            nodes_expanded: exploration.expanded_count,
+            timed_out: exploration.timed_out,
+            ..SearchResult::default()
+        };
+    };
+
+    let node_ids = reconstruct_node_ids(&exploration.predecessors, src_node.id, end_node_id);
+    let distance = node_ids
+        .windows(2)
+        .map(|pair| edge_weight(mode, &exploration.nodes.get(pair[0]).unwrap(), &exploration.nodes.get(pair[1]).unwrap()))
+        .sum();
+
+    SearchResult {
+        path: path_coordinates(source, mode, &node_ids, &exploration.nodes),
+        node_ids,
+        distance,
+        duration,
+        nodes_expanded: exploration.expanded_count,
+        timed_out: exploration.timed_out,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hours(value: &str) -> Option<f64> {
+        parse_departure_time(value).map(|seconds| seconds / SECONDS_PER_HOUR)
+    }
+
+    #[test]
+    fn offsets_convert_to_new_york_time() {
+        assert_eq!(hours("2024-07-01T12:00:00Z"), Some(8.0)); // EDT, UTC-4
+        assert_eq!(hours("2024-01-15T12:00:00Z"), Some(7.0)); // EST, UTC-5
+        assert_eq!(hours("2024-07-01T17:30:00+02:00"), Some(11.5));
+        assert_eq!(hours("2024-07-01T08:15:00-04:00"), Some(8.25));
+    }
+
+    #[test]
+    fn times_without_offset_are_local() {
+        assert_eq!(hours("2024-07-01T08:15:00"), Some(8.25));
+        assert_eq!(hours("2024-07-01T08:15"), Some(8.25));
+        assert_eq!(hours("17:45:00"), Some(17.75));
+        assert_eq!(hours("17:45"), Some(17.75));
+        assert_eq!(hours("quarter past eight"), None);
+    }
+}
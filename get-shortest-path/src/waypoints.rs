@@ -0,0 +1,286 @@
+// Visiting order for multi-stop routes. The first point always stays first; the last
+// one optionally stays last; every stop in between may be reordered.
+
+// Up to this many reorderable stops the order is found exactly (Held-Karp, O(2^n n^2))
+const EXACT_MAX_STOPS: usize = 10;
+
+// Stand-in for unreachable pairs: orders that avoid them always win
+const UNREACHABLE_COST: f64 = 1e12;
+
+fn cost(matrix: &[Vec<Option<f64>>], from: usize, to: usize) -> f64 {
+    matrix[from][to].unwrap_or(UNREACHABLE_COST)
+}
+
+fn order_cost(matrix: &[Vec<Option<f64>>], order: &[usize]) -> f64 {
+    order.windows(2).map(|pair| cost(matrix, pair[0], pair[1])).sum()
+}
+
+/// Order in which to visit the points of an N x N distance `matrix`, as indices.
+/// Starts at 0 and, with `keep_last`, ends at N - 1.
+pub fn optimize_order(matrix: &[Vec<Option<f64>>], keep_last: bool) -> Vec<usize> {
+    let point_count = matrix.len();
+    let last = if keep_last && point_count > 1 { Some(point_count - 1) } else { None };
+    let stops: Vec<usize> = (1..point_count).filter(|point| Some(*point) != last).collect();
+
+    let mut order = if stops.len() <= EXACT_MAX_STOPS {
+        exact_order(matrix, &stops, last)
+    } else {
+        two_opt(matrix, nearest_neighbor_order(matrix, &stops), last.is_some())
+    };
+    order.insert(0, 0);
+    order.extend(last);
+    order
+}
+
+// Held-Karp over subsets of stops: best[mask][j] is the cheapest way to leave point 0,
+// visit exactly the stops in `mask` and stand at stop j
+fn exact_order(matrix: &[Vec<Option<f64>>], stops: &[usize], last: Option<usize>) -> Vec<usize> {
+    let stop_count = stops.len();
+    if stop_count == 0 {
+        return Vec::new();
+    }
+
+    let subsets = 1usize << stop_count;
+    let mut best = vec![vec![f64::INFINITY; stop_count]; subsets];
+    let mut previous = vec![vec![usize::MAX; stop_count]; subsets];
+    for (j, stop) in stops.iter().enumerate() {
+        best[1 << j][j] = cost(matrix, 0, *stop);
+    }
+
+    for mask in 1..subsets {
+        for j in 0..stop_count {
+            if mask & (1 << j) == 0 || best[mask][j].is_infinite() {
+                continue;
+            }
+            for k in 0..stop_count {
+                if mask & (1 << k) != 0 {
+                    continue;
+                }
+                let next_mask = mask | (1 << k);
+                let next_cost = best[mask][j] + cost(matrix, stops[j], stops[k]);
+                if next_cost < best[next_mask][k] {
+                    best[next_mask][k] = next_cost;
+                    previous[next_mask][k] = j;
+                }
+            }
+        }
+    }
+
+    // Close the path at the fixed last point when there is one
+    let full = subsets - 1;
+    let mut end = (0..stop_count)
+        .min_by(|a, b| {
+            let total = |j: usize| best[full][j] + last.map(|last| cost(matrix, stops[j], last)).unwrap_or(0.0);
+            total(*a).total_cmp(&total(*b))
+        })
+        .unwrap();
+
+    let mut order = Vec::with_capacity(stop_count);
+    let mut mask = full;
+    loop {
+        order.push(stops[end]);
+        let before = previous[mask][end];
+        mask &= !(1 << end);
+        if before == usize::MAX {
+            break;
+        }
+        end = before;
+    }
+    order.reverse();
+    order
+}
+
+// Greedy start: always go to the closest stop not visited yet
+fn nearest_neighbor_order(matrix: &[Vec<Option<f64>>], stops: &[usize]) -> Vec<usize> {
+    let mut remaining = stops.to_vec();
+    let mut order = Vec::with_capacity(stops.len());
+    let mut current = 0;
+    while !remaining.is_empty() {
+        let (index, _) = remaining
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| cost(matrix, current, **a).total_cmp(&cost(matrix, current, **b)))
+            .unwrap();
+        current = remaining.swap_remove(index);
+        order.push(current);
+    }
+    order
+}
+
+// Reverse stretches of stops while that shortens the route. Costs are recomputed over
+// the whole path since one-way streets make the matrix asymmetric.
+fn two_opt(matrix: &[Vec<Option<f64>>], stops: Vec<usize>, keep_last: bool) -> Vec<usize> {
+    let point_count = matrix.len();
+    let with_ends = |stops: &[usize]| {
+        let mut path = Vec::with_capacity(stops.len() + 2);
+        path.push(0);
+        path.extend_from_slice(stops);
+        if keep_last {
+            path.push(point_count - 1);
+        }
+        path
+    };
+
+    let mut order = stops;
+    let mut best_cost = order_cost(matrix, &with_ends(&order));
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..order.len() {
+            for j in i + 1..order.len() {
+                order[i..=j].reverse();
+                let candidate_cost = order_cost(matrix, &with_ends(&order));
+                if candidate_cost + 1e-9 < best_cost {
+                    best_cost = candidate_cost;
+                    improved = true;
+                } else {
+                    order[i..=j].reverse();
+                }
+            }
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Asymmetric distances like one-way streets produce, from a fixed pseudo-random sequence
+    fn random_matrix(point_count: usize, seed: u64) -> Vec<Vec<Option<f64>>> {
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as f64 / (1u64 << 31) as f64
+        };
+        (0..point_count)
+            .map(|from| {
+                (0..point_count)
+                    .map(|to| if from == to { Some(0.0) } else { Some(100.0 + 900.0 * next()) })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Points on a line, 100 m apart
+    fn line_matrix(point_count: usize) -> Vec<Vec<Option<f64>>> {
+        (0..point_count)
+            .map(|from| (0..point_count).map(|to| Some(100.0 * (from as f64 - to as f64).abs())).collect())
+            .collect()
+    }
+
+    // Cheapest cost over every order of the reorderable stops
+    fn brute_force_cost(matrix: &[Vec<Option<f64>>], keep_last: bool) -> f64 {
+        fn permute(stops: &mut Vec<usize>, k: usize, visit: &mut impl FnMut(&[usize])) {
+            if k == stops.len() {
+                visit(stops);
+                return;
+            }
+            for i in k..stops.len() {
+                stops.swap(k, i);
+                permute(stops, k + 1, visit);
+                stops.swap(k, i);
+            }
+        }
+
+        let point_count = matrix.len();
+        let mut stops: Vec<usize> =
+            (1..point_count).filter(|point| !keep_last || *point != point_count - 1).collect();
+        let mut best = f64::INFINITY;
+        permute(&mut stops, 0, &mut |stops| {
+            let mut order = vec![0];
+            order.extend_from_slice(stops);
+            if keep_last {
+                order.push(point_count - 1);
+            }
+            best = best.min(order_cost(matrix, &order));
+        });
+        best
+    }
+
+    fn assert_permutation(order: &[usize], point_count: usize) {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..point_count).collect::<Vec<_>>());
+        assert_eq!(order[0], 0);
+    }
+
+    #[test]
+    fn exact_order_is_optimal() {
+        for seed in 0..20 {
+            let matrix = random_matrix(8, seed);
+            for keep_last in [false, true] {
+                let order = optimize_order(&matrix, keep_last);
+                assert_permutation(&order, matrix.len());
+                let expected = brute_force_cost(&matrix, keep_last);
+                assert!(
+                    (order_cost(&matrix, &order) - expected).abs() < 1e-6,
+                    "seed {} keep_last {}: {:?} costs {:.3}, best is {:.3}",
+                    seed,
+                    keep_last,
+                    order,
+                    order_cost(&matrix, &order),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn heuristic_above_exact_limit() {
+        let matrix = random_matrix(EXACT_MAX_STOPS + 3, 7);
+        let stops: Vec<usize> = (1..matrix.len()).collect();
+        let mut expected = vec![0];
+        expected.extend(two_opt(&matrix, nearest_neighbor_order(&matrix, &stops), false));
+        let order = optimize_order(&matrix, false);
+        assert_permutation(&order, matrix.len());
+        assert_eq!(order, expected);
+
+        // Stops along a street come out in street order
+        assert_eq!(optimize_order(&line_matrix(20), false), (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keep_last_pins_the_destination() {
+        // The destination sits right next to the start, the stops far away
+        let distance = |a: usize, b: usize| match (a.min(b), a.max(b)) {
+            (near, far) if near == far => 0.0,
+            (stop, 4) => 10.0 + 1000.0 * stop as f64,
+            (near, far) => 100.0 * (far - near) as f64,
+        };
+        let matrix: Vec<Vec<Option<f64>>> = (0..5).map(|a| (0..5).map(|b| Some(distance(a, b))).collect()).collect();
+        let order = optimize_order(&matrix, true);
+        assert_eq!(order.last(), Some(&4));
+        assert!((order_cost(&matrix, &order) - brute_force_cost(&matrix, true)).abs() < 1e-6);
+        // Free to end anywhere, the route visits the destination first
+        assert_eq!(optimize_order(&matrix, false), [0, 4, 1, 2, 3]);
+
+        let large = random_matrix(EXACT_MAX_STOPS + 4, 3);
+        let order = optimize_order(&large, true);
+        assert_permutation(&order, large.len());
+        assert_eq!(order.last(), Some(&(large.len() - 1)));
+    }
+
+    #[test]
+    fn handles_zero_one_and_two_stops() {
+        assert_eq!(optimize_order(&line_matrix(1), false), [0]);
+        assert_eq!(optimize_order(&line_matrix(1), true), [0]);
+        assert_eq!(optimize_order(&line_matrix(2), false), [0, 1]);
+        assert_eq!(optimize_order(&line_matrix(2), true), [0, 1]);
+        assert_eq!(optimize_order(&line_matrix(3), true), [0, 1, 2]);
+
+        // Two stops, the farther one listed first
+        let matrix = vec![
+            vec![Some(0.0), Some(200.0), Some(100.0)],
+            vec![Some(200.0), Some(0.0), Some(100.0)],
+            vec![Some(100.0), Some(100.0), Some(0.0)],
+        ];
+        assert_eq!(optimize_order(&matrix, false), [0, 2, 1]);
+        assert_eq!(optimize_order(&line_matrix(4), true), [0, 1, 2, 3]);
+
+        // An unreachable leg is avoided whenever another order exists
+        let mut matrix = line_matrix(3);
+        matrix[0][1] = None;
+        assert_eq!(optimize_order(&matrix, false), [0, 2, 1]);
+    }
+}
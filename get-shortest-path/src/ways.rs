@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A routable OSM way as stored in `planet_osm_ways` (osm2pgsql slim layout).
+#[derive(Clone, Debug, Deserialize, FromRow)]
+pub struct OsmWay {
+    pub id: i64,
+    pub nodes: Vec<i64>,
+    // Flattened key / value pairs
+    pub tags: Option<Vec<String>>,
+}
+
+impl OsmWay {
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .as_deref()?
+            .chunks_exact(2)
+            .find(|pair| pair[0] == key)
+            .map(|pair| pair[1].as_str())
+    }
+}
+
+/// The tags of a way the router reports and matches against.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct WayTags {
+    pub id: i64,
+    pub name: Option<String>,
+    pub highway: Option<String>,
+}
+
+impl From<&OsmWay> for WayTags {
+    fn from(way: &OsmWay) -> WayTags {
+        WayTags {
+            id: way.id,
+            name: way.tag("name").map(str::to_string),
+            highway: way.tag("highway").map(str::to_string),
+        }
+    }
+}
+
+// output_ways.txt holds one Python dict literal per way:
+// {'osm_id': '1', 'type': 'way', 'nodes': ['2', '3'], 'tags': {'name': 'Nassau Street'}}
+// output_nodes.txt uses the same layout with bare numbers for the coordinates.
+#[derive(Debug)]
+pub(crate) enum Literal {
+    Str(String),
+    // Numbers, True, False and None, kept as written
+    Bare(String),
+    List(Vec<Literal>),
+    Dict(Vec<(String, Literal)>),
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let quote = chars.next().filter(|c| *c == '\'' || *c == '"')?;
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                escaped => value.push(escaped),
+            },
+            c if c == quote => return Some(value),
+            c => value.push(c),
+        }
+    }
+}
+
+fn parse_literal(chars: &mut Peekable<Chars>) -> Option<Literal> {
+    skip_whitespace(chars);
+    match *chars.peek()? {
+        '\'' | '"' => parse_string(chars).map(Literal::Str),
+        '[' => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Some(Literal::List(items));
+                }
+                items.push(parse_literal(chars)?);
+                skip_whitespace(chars);
+                chars.next_if_eq(&',');
+            }
+        }
+        '{' => {
+            chars.next();
+            let mut entries = Vec::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.next_if_eq(&'}').is_some() {
+                    return Some(Literal::Dict(entries));
+                }
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                chars.next_if_eq(&':')?;
+                entries.push((key, parse_literal(chars)?));
+                skip_whitespace(chars);
+                chars.next_if_eq(&',');
+            }
+        }
+        _ => {
+            let mut token = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || matches!(c, '-' | '+' | '.')) {
+                token.push(c);
+            }
+            (!token.is_empty()).then_some(Literal::Bare(token))
+        }
+    }
+}
+
+// Top-level dict of one dump line, None for headers and anything unparseable
+pub(crate) fn parse_dict_line(line: &str) -> Option<Vec<(String, Literal)>> {
+    match parse_literal(&mut line.chars().peekable())? {
+        Literal::Dict(entries) => Some(entries),
+        _ => None,
+    }
+}
+
+fn parse_way_line(line: &str) -> Option<OsmWay> {
+    let entries = parse_dict_line(line)?;
+
+    let mut way = OsmWay { id: 0, nodes: Vec::new(), tags: None };
+    for (key, value) in entries {
+        match (key.as_str(), value) {
+            ("osm_id", Literal::Str(id)) => way.id = id.parse().ok()?,
+            ("nodes", Literal::List(nodes)) => {
+                for node in nodes {
+                    let Literal::Str(id) = node else { return None };
+                    way.nodes.push(id.parse().ok()?);
+                }
+            }
+            ("tags", Literal::Dict(tags)) => {
+                let mut tags: Vec<(String, String)> = tags
+                    .into_iter()
+                    .filter_map(|(key, value)| match value {
+                        Literal::Str(value) => Some((key, value)),
+                        _ => None,
+                    })
+                    .collect();
+                tags.sort();
+                way.tags = Some(tags.into_iter().flat_map(|(key, value)| [key, value]).collect());
+            }
+            _ => {}
+        }
+    }
+    Some(way)
+}
+
+/// Read the ways dumped to output_ways.txt, the CSV backend's source of way tags.
+/// Header and unparseable lines are skipped.
+pub fn read_ways_txt(path: &str) -> Result<Vec<OsmWay>, io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut ways = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('{') {
+            ways.extend(parse_way_line(&line));
+        }
+    }
+    Ok(ways)
+}
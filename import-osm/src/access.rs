@@ -0,0 +1,147 @@
+// Per-mode traversal direction of a way, derived from its OSM tags.
+// Pedestrians are never restricted, so only cars and bikes are classified here.
+
+use std::collections::HashMap;
+
+// Ways that only pedestrians (and sometimes bikes) may use
+const CAR_FORBIDDEN_HIGHWAYS: &[&str] = &[
+    "footway", "path", "cycleway", "bridleway", "steps", "corridor", "pedestrian", "track",
+];
+
+// Ways bikes may not use unless tagged bicycle=yes/designated
+const BIKE_FORBIDDEN_HIGHWAYS: &[&str] = &[
+    "motorway", "motorway_link", "steps", "corridor", "footway", "pedestrian",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Both,
+    Forward,
+    Backward,
+    Forbidden,
+}
+
+impl Direction {
+    pub fn allows_forward(self) -> bool {
+        matches!(self, Direction::Both | Direction::Forward)
+    }
+
+    pub fn allows_backward(self) -> bool {
+        matches!(self, Direction::Both | Direction::Backward)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WayAccess {
+    pub car: Direction,
+    pub bike: Direction,
+}
+
+fn tag<'a>(tags: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    tags.get(key).map(|value| value.as_str())
+}
+
+// oneway=yes/-1 win; otherwise roundabouts and motorways are implicitly oneway
+fn oneway(tags: &HashMap<String, String>) -> Direction {
+    match tag(tags, "oneway") {
+        Some("yes") | Some("true") | Some("1") => return Direction::Forward,
+        Some("-1") | Some("reverse") => return Direction::Backward,
+        Some("no") | Some("false") | Some("0") => return Direction::Both,
+        _ => {}
+    }
+
+    let implied_oneway = matches!(tag(tags, "junction"), Some("roundabout") | Some("circular"))
+        || tag(tags, "highway") == Some("motorway");
+
+    if implied_oneway {
+        Direction::Forward
+    } else {
+        Direction::Both
+    }
+}
+
+fn is_denied(value: Option<&str>) -> bool {
+    matches!(value, Some("no") | Some("private"))
+}
+
+fn car_access(tags: &HashMap<String, String>, highway: &str) -> Direction {
+    let denied = CAR_FORBIDDEN_HIGHWAYS.contains(&highway)
+        || is_denied(tag(tags, "motor_vehicle"))
+        || is_denied(tag(tags, "motorcar"))
+        || tag(tags, "access") == Some("no");
+
+    if denied {
+        Direction::Forbidden
+    } else {
+        oneway(tags)
+    }
+}
+
+fn bike_access(tags: &HashMap<String, String>, highway: &str) -> Direction {
+    let bicycle = tag(tags, "bicycle");
+    let explicitly_allowed = matches!(bicycle, Some("yes") | Some("designated") | Some("permissive"));
+    let denied = is_denied(bicycle)
+        || (BIKE_FORBIDDEN_HIGHWAYS.contains(&highway) && !explicitly_allowed)
+        || (tag(tags, "access") == Some("no") && !explicitly_allowed);
+
+    if denied {
+        return Direction::Forbidden;
+    }
+
+    // Contraflow lanes let bikes ride against a oneway
+    let contraflow = tag(tags, "oneway:bicycle") == Some("no")
+        || tag(tags, "cycleway")
+            .map(|cycleway| cycleway.starts_with("opposite"))
+            .unwrap_or(false);
+
+    if contraflow {
+        Direction::Both
+    } else {
+        oneway(tags)
+    }
+}
+
+pub fn way_access(tags: &HashMap<String, String>) -> WayAccess {
+    let highway = tag(tags, "highway").unwrap_or_default();
+    WayAccess {
+        car: car_access(tags, highway),
+        bike: bike_access(tags, highway),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Direction::*;
+
+    // Tags of a way, then the expected car and bike directions
+    type Case = (&'static [(&'static str, &'static str)], Direction, Direction);
+
+    #[test]
+    fn directions_follow_tags() {
+        let cases: &[Case] = &[
+            (&[("highway", "residential")], Both, Both),
+            (&[("highway", "residential"), ("oneway", "yes")], Forward, Forward),
+            (&[("highway", "residential"), ("oneway", "true")], Forward, Forward),
+            (&[("highway", "residential"), ("oneway", "1")], Forward, Forward),
+            (&[("highway", "residential"), ("oneway", "-1")], Backward, Backward),
+            (&[("highway", "residential"), ("oneway", "no")], Both, Both),
+            (&[("highway", "primary"), ("junction", "roundabout")], Forward, Forward),
+            (&[("highway", "primary"), ("junction", "roundabout"), ("oneway", "no")], Both, Both),
+            (&[("highway", "motorway")], Forward, Forbidden),
+            (&[("highway", "motorway"), ("oneway", "no")], Both, Forbidden),
+            (&[("highway", "residential"), ("oneway", "yes"), ("oneway:bicycle", "no")], Forward, Both),
+            (&[("highway", "residential"), ("oneway", "-1"), ("cycleway", "opposite_lane")], Backward, Both),
+            (&[("highway", "footway")], Forbidden, Forbidden),
+            (&[("highway", "cycleway"), ("oneway", "yes")], Forbidden, Forward),
+            (&[("highway", "residential"), ("access", "no"), ("bicycle", "yes")], Forbidden, Both),
+        ];
+
+        for (tags, car, bike) in cases {
+            let tags: HashMap<String, String> =
+                tags.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+            let access = way_access(&tags);
+            assert_eq!((access.car, access.bike), (*car, *bike), "{:?}", tags);
+        }
+    }
+}
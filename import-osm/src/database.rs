@@ -0,0 +1,156 @@
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Postgres, QueryBuilder};
+use std::io;
+
+// Rows per INSERT statement, keeps each statement well under the Postgres bind limit
+const INSERT_CHUNK_SIZE: usize = 5000;
+
+#[derive(Clone, Debug)]
+pub struct ImportNode {
+    pub id: i64,
+    pub lat: f64,
+    pub lon: f64,
+    pub adjacency_list: Vec<i64>,
+    pub adjacency_lengths: Vec<f64>,
+    pub car_adjacency_list: Vec<i64>,
+    pub bike_adjacency_list: Vec<i64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportWay {
+    pub id: i64,
+    pub refs: Vec<i64>,
+    // Flattened key / value pairs, the osm2pgsql slim layout
+    pub tags: Vec<String>,
+}
+
+pub async fn create_pool(database_url: &str) -> Result<sqlx::PgPool, io::Error> {
+    PgPoolOptions::new()
+        .max_connections(5)
+        .connect(database_url)
+        .await
+        .map_err(|err| io::Error::other(err.to_string())) // Convert sqlx::Error to io::Error
+}
+
+async fn execute(pool: &sqlx::PgPool, query: &str) -> Result<(), io::Error> {
+    sqlx::query(query)
+        .execute(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    Ok(())
+}
+
+// planet_osm_nodes keeps the osm2pgsql layout (coordinates as 1e7 fixed point) so existing
+// databases can be imported into in place; adjacent_nodes is always rebuilt from scratch.
+// planet_osm_ways follows the same layout and keeps the tags (name, highway) of routable ways.
+// nodes is the undirected walking adjacency with edge lengths in meters alongside in lengths,
+// car_nodes / bike_nodes only hold the neighbors that may be reached from id under oneway rules
+pub async fn create_tables(pool: &sqlx::PgPool) -> Result<(), io::Error> {
+    execute(
+        pool,
+        r#"
+        CREATE TABLE IF NOT EXISTS planet_osm_nodes (
+            id BIGINT PRIMARY KEY,
+            lat INTEGER NOT NULL,
+            lon INTEGER NOT NULL
+        );
+    "#,
+    )
+    .await?;
+
+    execute(
+        pool,
+        r#"
+        CREATE TABLE IF NOT EXISTS planet_osm_ways (
+            id BIGINT PRIMARY KEY,
+            nodes BIGINT[] NOT NULL,
+            tags TEXT[]
+        );
+    "#,
+    )
+    .await?;
+
+    execute(pool, "DROP TABLE IF EXISTS adjacent_nodes;").await?;
+    execute(
+        pool,
+        r#"
+        CREATE TABLE adjacent_nodes (
+            id BIGINT PRIMARY KEY,
+            nodes BIGINT[],
+            lengths FLOAT8[],
+            car_nodes BIGINT[],
+            bike_nodes BIGINT[]
+        );
+    "#,
+    )
+    .await?;
+
+    println!("Tables planet_osm_nodes, planet_osm_ways and adjacent_nodes ready.");
+    Ok(())
+}
+
+pub async fn insert_nodes(pool: &sqlx::PgPool, nodes: &[ImportNode]) -> Result<(), io::Error> {
+    for chunk in nodes.chunks(INSERT_CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> =
+            QueryBuilder::new("INSERT INTO planet_osm_nodes (id, lat, lon) ");
+        builder.push_values(chunk, |mut row, node| {
+            row.push_bind(node.id)
+                .push_bind((node.lat * 1e7).round() as i32)
+                .push_bind((node.lon * 1e7).round() as i32);
+        });
+        builder.push(" ON CONFLICT (id) DO UPDATE SET lat = EXCLUDED.lat, lon = EXCLUDED.lon");
+
+        builder
+            .build()
+            .execute(pool)
+            .await
+            .map_err(|err| io::Error::other(err.to_string()))?;
+    }
+
+    println!("Inserted {} rows into planet_osm_nodes.", nodes.len());
+    Ok(())
+}
+
+pub async fn insert_ways(pool: &sqlx::PgPool, ways: &[ImportWay]) -> Result<(), io::Error> {
+    for chunk in ways.chunks(INSERT_CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("INSERT INTO planet_osm_ways (id, nodes, tags) ");
+        builder.push_values(chunk, |mut row, way| {
+            row.push_bind(way.id)
+                .push_bind(way.refs.clone())
+                .push_bind(way.tags.clone());
+        });
+        builder.push(" ON CONFLICT (id) DO UPDATE SET nodes = EXCLUDED.nodes, tags = EXCLUDED.tags");
+
+        builder
+            .build()
+            .execute(pool)
+            .await
+            .map_err(|err| io::Error::other(err.to_string()))?;
+    }
+
+    println!("Inserted {} rows into planet_osm_ways.", ways.len());
+    Ok(())
+}
+
+pub async fn insert_adjacent_nodes(pool: &sqlx::PgPool, nodes: &[ImportNode]) -> Result<(), io::Error> {
+    for chunk in nodes.chunks(INSERT_CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> =
+            QueryBuilder::new("INSERT INTO adjacent_nodes (id, nodes, lengths, car_nodes, bike_nodes) ");
+        builder.push_values(chunk, |mut row, node| {
+            row.push_bind(node.id)
+                .push_bind(node.adjacency_list.clone())
+                .push_bind(node.adjacency_lengths.clone())
+                .push_bind(node.car_adjacency_list.clone())
+                .push_bind(node.bike_adjacency_list.clone());
+        });
+
+        builder
+            .build()
+            .execute(pool)
+            .await
+            .map_err(|err| io::Error::other(err.to_string()))?;
+    }
+
+    println!("Inserted {} rows into adjacent_nodes.", nodes.len());
+    Ok(())
+}
@@ -0,0 +1,207 @@
+pub mod access;
+pub mod database;
+pub mod pbf;
+
+use crate::access::way_access;
+use crate::database::{ImportNode, ImportWay};
+use crate::pbf::{Element, Way};
+
+use dotenv::dotenv;
+use geoutils::Location;
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::time::Instant;
+
+// highway=* values that can be walked, cycled or driven on; everything else
+// (proposed, construction, platform, raceway, ...) is left out of the graph
+const ROUTABLE_HIGHWAYS: &[&str] = &[
+    "motorway", "motorway_link", "trunk", "trunk_link", "primary", "primary_link",
+    "secondary", "secondary_link", "tertiary", "tertiary_link", "unclassified",
+    "residential", "living_street", "service", "pedestrian", "track", "road",
+    "footway", "path", "cycleway", "bridleway", "steps", "corridor",
+];
+
+// Out-neighbors of one node, per travel mode
+#[derive(Debug, Default)]
+struct Adjacency {
+    foot: Vec<i64>,
+    car: Vec<i64>,
+    bike: Vec<i64>,
+}
+
+#[derive(Debug, Default)]
+struct ImportStats {
+    nodes: usize,
+    ways: usize,
+    skipped_nodes: usize,
+    skipped_ways: usize,
+    skipped_relations: usize,
+    missing_refs: usize,
+    oneway_ways: usize,
+}
+
+pub fn load_config() -> String {
+    dotenv().ok();
+    env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+}
+
+fn is_routable(way: &Way) -> bool {
+    way.tags
+        .get("highway")
+        .map(|highway| ROUTABLE_HIGHWAYS.contains(&highway.as_str()))
+        .unwrap_or(false)
+}
+
+// Same Vincenty distance the router used to compute on every relaxation, now paid once at import
+fn edge_length(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let from = Location::new(from.0, from.1);
+    let to = Location::new(to.0, to.1);
+    from.distance_to(&to)
+        .unwrap_or_else(|_| from.haversine_distance_to(&to))
+        .meters()
+}
+
+fn add_neighbor(neighbors: &mut Vec<i64>, node_id: i64, neighbor_id: i64) {
+    if node_id != neighbor_id && !neighbors.contains(&neighbor_id) {
+        neighbors.push(neighbor_id);
+    }
+}
+
+// Read the file twice: routable ways first to learn which nodes matter, then only those nodes' coordinates
+fn build_nodes(path: &str) -> io::Result<(Vec<ImportNode>, Vec<ImportWay>, ImportStats)> {
+    let mut stats = ImportStats::default();
+    let mut adjacency: HashMap<i64, Adjacency> = HashMap::new();
+    let mut ways = Vec::new();
+
+    pbf::read_elements(path, |element| match element {
+        Element::Way(way) if is_routable(&way) => {
+            stats.ways += 1;
+            let access = way_access(&way.tags);
+            if access.car.allows_forward() != access.car.allows_backward() {
+                stats.oneway_ways += 1;
+            }
+
+            // Each node is adjacent to the previous and next node of the way on foot,
+            // cars and bikes only get the directions the way's tags allow
+            for pair in way.refs.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+
+                let from = adjacency.entry(a).or_default();
+                add_neighbor(&mut from.foot, a, b);
+                if access.car.allows_forward() {
+                    add_neighbor(&mut from.car, a, b);
+                }
+                if access.bike.allows_forward() {
+                    add_neighbor(&mut from.bike, a, b);
+                }
+
+                let to = adjacency.entry(b).or_default();
+                add_neighbor(&mut to.foot, b, a);
+                if access.car.allows_backward() {
+                    add_neighbor(&mut to.car, b, a);
+                }
+                if access.bike.allows_backward() {
+                    add_neighbor(&mut to.bike, b, a);
+                }
+            }
+
+            let mut tags: Vec<(String, String)> = way.tags.into_iter().collect();
+            tags.sort();
+            ways.push(ImportWay {
+                id: way.id,
+                refs: way.refs,
+                tags: tags.into_iter().flat_map(|(key, value)| [key, value]).collect(),
+            });
+        }
+        Element::Way(_) => stats.skipped_ways += 1,
+        Element::Relation(_) => stats.skipped_relations += 1,
+        Element::Node(_) => {}
+    })?;
+
+    let mut coordinates: HashMap<i64, (f64, f64)> = HashMap::new();
+    pbf::read_elements(path, |element| {
+        if let Element::Node(node) = element {
+            if adjacency.contains_key(&node.id) {
+                coordinates.insert(node.id, (node.lat, node.lon));
+            } else {
+                stats.skipped_nodes += 1;
+            }
+        }
+    })?;
+
+    // Extracts can cut ways at the boundary, drop refs to nodes that are not in the file
+    let mut nodes = Vec::with_capacity(coordinates.len());
+    for (id, mut neighbors) in adjacency {
+        let Some(&(lat, lon)) = coordinates.get(&id) else {
+            stats.missing_refs += 1;
+            continue;
+        };
+        for list in [&mut neighbors.foot, &mut neighbors.car, &mut neighbors.bike] {
+            list.retain(|neighbor_id| coordinates.contains_key(neighbor_id));
+        }
+        let adjacency_lengths = neighbors
+            .foot
+            .iter()
+            .map(|neighbor_id| edge_length((lat, lon), coordinates[neighbor_id]))
+            .collect();
+        nodes.push(ImportNode {
+            id,
+            lat,
+            lon,
+            adjacency_list: neighbors.foot,
+            adjacency_lengths,
+            car_adjacency_list: neighbors.car,
+            bike_adjacency_list: neighbors.bike,
+        });
+    }
+    nodes.sort_by_key(|node| node.id);
+    stats.nodes = nodes.len();
+    ways.sort_by_key(|way| way.id);
+
+    Ok((nodes, ways, stats))
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let osm_file_path = args
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .cloned()
+        .unwrap_or_else(|| "map_mini.pbf".to_string());
+
+    let parse_start = Instant::now();
+    let (nodes, ways, stats) = build_nodes(&osm_file_path)?;
+    println!("Finished reading and processing file: {} in {:?}", osm_file_path, parse_start.elapsed());
+
+    println!("Number of nodes: {}", stats.nodes);
+    println!("Number of ways: {} ({} oneway for cars)", stats.ways, stats.oneway_ways);
+    println!(
+        "Skipped elements: {} nodes, {} ways, {} relations",
+        stats.skipped_nodes, stats.skipped_ways, stats.skipped_relations
+    );
+    if stats.missing_refs > 0 {
+        println!("Dropped {} node refs missing from the file", stats.missing_refs);
+    }
+
+    if dry_run {
+        println!("Dry run, nothing written to the database.");
+        return Ok(());
+    }
+
+    // Load database configuration
+    let config = load_config();
+
+    // Create a connection pool
+    let pool = database::create_pool(&config).await?;
+
+    database::create_tables(&pool).await?;
+    database::insert_nodes(&pool, &nodes).await?;
+    database::insert_ways(&pool, &ways).await?;
+    database::insert_adjacent_nodes(&pool, &nodes).await?;
+
+    drop(pool); // This will close all connections in the pool
+    Ok(())
+}
@@ -0,0 +1,421 @@
+// Minimal reader for the OSM PBF format (https://wiki.openstreetmap.org/wiki/PBF_Format).
+// Only the parts the importer needs are decoded: node coordinates, way node refs and way tags.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub id: i64,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Way {
+    pub id: i64,
+    pub refs: Vec<i64>,
+    pub tags: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Element {
+    Node(Node),
+    Way(Way),
+    Relation(i64),
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Protobuf wire-format cursor over a decoded message
+struct Message<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+impl<'a> Message<'a> {
+    fn new(data: &'a [u8]) -> Message<'a> {
+        Message { data, pos: 0 }
+    }
+
+    fn read_varint(&mut self) -> io::Result<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid_data("truncated varint"))?;
+            self.pos += 1;
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+            if shift > 63 {
+                return Err(invalid_data("varint too long"));
+            }
+        }
+    }
+
+    // The next `len` bytes, failing instead of reading past the end of the message
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid_data("truncated field"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    // Returns the next (field number, value) pair, or None at the end of the message
+    fn next_field(&mut self) -> io::Result<Option<(u64, Value<'a>)>> {
+        if self.pos >= self.data.len() {
+            return Ok(None);
+        }
+
+        let key = self.read_varint()?;
+        let field = key >> 3;
+        let value = match key & 0x7 {
+            0 => Value::Varint(self.read_varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = usize::try_from(self.read_varint()?).map_err(|_| invalid_data("field too long"))?;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            _ => return Err(invalid_data("unsupported wire type")),
+        };
+
+        Ok(Some((field, value)))
+    }
+}
+
+fn zigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn packed_varints(bytes: &[u8]) -> io::Result<Vec<u64>> {
+    let mut message = Message::new(bytes);
+    let mut values = Vec::new();
+    while message.pos < bytes.len() {
+        values.push(message.read_varint()?);
+    }
+    Ok(values)
+}
+
+// Delta-coded packed sint64 field, as used by DenseNodes and Way.refs
+fn packed_deltas(bytes: &[u8]) -> io::Result<Vec<i64>> {
+    let mut current = 0i64;
+    Ok(packed_varints(bytes)?
+        .into_iter()
+        .map(|value| {
+            current += zigzag(value);
+            current
+        })
+        .collect())
+}
+
+struct BlockContext {
+    strings: Vec<String>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+}
+
+impl BlockContext {
+    fn coordinate(&self, offset: i64, value: i64) -> f64 {
+        (offset + self.granularity * value) as f64 * 1e-9
+    }
+
+    fn string(&self, index: u64) -> String {
+        self.strings.get(index as usize).cloned().unwrap_or_default()
+    }
+}
+
+fn read_blob(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut message = Message::new(data);
+    let mut raw = None;
+    let mut zlib = None;
+    let mut raw_size = 0;
+
+    while let Some((field, value)) = message.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(bytes)) => raw = Some(bytes),
+            (2, Value::Varint(size)) => raw_size = size as usize,
+            (3, Value::Bytes(bytes)) => zlib = Some(bytes),
+            (4..=7, Value::Bytes(_)) => return Err(invalid_data("unsupported blob compression")),
+            _ => {}
+        }
+    }
+
+    if let Some(bytes) = raw {
+        return Ok(bytes.to_vec());
+    }
+
+    let bytes = zlib.ok_or_else(|| invalid_data("blob has no data"))?;
+    let mut decoded = Vec::with_capacity(raw_size);
+    ZlibDecoder::new(bytes).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+fn read_dense_nodes(data: &[u8], context: &BlockContext, handler: &mut impl FnMut(Element)) -> io::Result<()> {
+    let mut message = Message::new(data);
+    let mut ids = Vec::new();
+    let mut lats = Vec::new();
+    let mut lons = Vec::new();
+
+    while let Some((field, value)) = message.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(bytes)) => ids = packed_deltas(bytes)?,
+            (8, Value::Bytes(bytes)) => lats = packed_deltas(bytes)?,
+            (9, Value::Bytes(bytes)) => lons = packed_deltas(bytes)?,
+            _ => {}
+        }
+    }
+
+    if ids.len() != lats.len() || ids.len() != lons.len() {
+        return Err(invalid_data("dense node arrays differ in length"));
+    }
+
+    for i in 0..ids.len() {
+        handler(Element::Node(Node {
+            id: ids[i],
+            lat: context.coordinate(context.lat_offset, lats[i]),
+            lon: context.coordinate(context.lon_offset, lons[i]),
+        }));
+    }
+
+    Ok(())
+}
+
+fn read_node(data: &[u8], context: &BlockContext) -> io::Result<Node> {
+    let mut message = Message::new(data);
+    let mut node = Node { id: 0, lat: 0.0, lon: 0.0 };
+
+    while let Some((field, value)) = message.next_field()? {
+        match (field, value) {
+            (1, Value::Varint(id)) => node.id = zigzag(id),
+            (8, Value::Varint(lat)) => node.lat = context.coordinate(context.lat_offset, zigzag(lat)),
+            (9, Value::Varint(lon)) => node.lon = context.coordinate(context.lon_offset, zigzag(lon)),
+            _ => {}
+        }
+    }
+
+    Ok(node)
+}
+
+fn read_way(data: &[u8], context: &BlockContext) -> io::Result<Way> {
+    let mut message = Message::new(data);
+    let mut id = 0;
+    let mut keys = Vec::new();
+    let mut vals = Vec::new();
+    let mut refs = Vec::new();
+
+    while let Some((field, value)) = message.next_field()? {
+        match (field, value) {
+            (1, Value::Varint(way_id)) => id = way_id as i64,
+            (2, Value::Bytes(bytes)) => keys = packed_varints(bytes)?,
+            (3, Value::Bytes(bytes)) => vals = packed_varints(bytes)?,
+            (8, Value::Bytes(bytes)) => refs = packed_deltas(bytes)?,
+            _ => {}
+        }
+    }
+
+    let tags = keys
+        .into_iter()
+        .zip(vals)
+        .map(|(key, val)| (context.string(key), context.string(val)))
+        .collect();
+
+    Ok(Way { id, refs, tags })
+}
+
+fn read_relation_id(data: &[u8]) -> io::Result<i64> {
+    let mut message = Message::new(data);
+    while let Some((field, value)) = message.next_field()? {
+        if let (1, Value::Varint(id)) = (field, value) {
+            return Ok(id as i64);
+        }
+    }
+    Err(invalid_data("relation without id"))
+}
+
+fn read_primitive_block(data: &[u8], handler: &mut impl FnMut(Element)) -> io::Result<()> {
+    let mut message = Message::new(data);
+    let mut groups = Vec::new();
+    let mut context = BlockContext {
+        strings: Vec::new(),
+        granularity: 100,
+        lat_offset: 0,
+        lon_offset: 0,
+    };
+
+    while let Some((field, value)) = message.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(bytes)) => {
+                let mut table = Message::new(bytes);
+                while let Some((_, entry)) = table.next_field()? {
+                    if let Value::Bytes(s) = entry {
+                        context.strings.push(String::from_utf8_lossy(s).into_owned());
+                    }
+                }
+            }
+            (2, Value::Bytes(bytes)) => groups.push(bytes),
+            (17, Value::Varint(granularity)) => context.granularity = granularity as i64,
+            (19, Value::Varint(offset)) => context.lat_offset = offset as i64,
+            (20, Value::Varint(offset)) => context.lon_offset = offset as i64,
+            _ => {}
+        }
+    }
+
+    // Groups are decoded after the whole block so the string table is always available
+    for group in groups {
+        let mut message = Message::new(group);
+        while let Some((field, value)) = message.next_field()? {
+            match (field, value) {
+                (1, Value::Bytes(bytes)) => handler(Element::Node(read_node(bytes, &context)?)),
+                (2, Value::Bytes(bytes)) => read_dense_nodes(bytes, &context, handler)?,
+                (3, Value::Bytes(bytes)) => handler(Element::Way(read_way(bytes, &context)?)),
+                (4, Value::Bytes(bytes)) => handler(Element::Relation(read_relation_id(bytes)?)),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Stream every node, way and relation in the file through `handler`
+pub fn read_elements(path: &str, mut handler: impl FnMut(Element)) -> io::Result<()> {
+    let mut file = File::open(path)?;
+
+    loop {
+        let mut len_bytes = [0u8; 4];
+        match file.read_exact(&mut len_bytes) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+
+        let mut header = vec![0u8; u32::from_be_bytes(len_bytes) as usize];
+        file.read_exact(&mut header)?;
+
+        let mut blob_type = String::new();
+        let mut data_size = 0;
+        let mut message = Message::new(&header);
+        while let Some((field, value)) = message.next_field()? {
+            match (field, value) {
+                (1, Value::Bytes(bytes)) => blob_type = String::from_utf8_lossy(bytes).into_owned(),
+                (3, Value::Varint(size)) => data_size = size as usize,
+                _ => {}
+            }
+        }
+
+        let mut blob = vec![0u8; data_size];
+        file.read_exact(&mut blob)?;
+
+        // OSMHeader blobs only describe the file, the data lives in OSMData blobs
+        if blob_type == "OSMData" {
+            read_primitive_block(&read_blob(&blob)?, &mut handler)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_varints_and_zigzag() {
+        let mut message = Message::new(&[0x01, 0xac, 0x02, 0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert_eq!(message.read_varint().unwrap(), 1);
+        assert_eq!(message.read_varint().unwrap(), 300);
+        assert_eq!(message.read_varint().unwrap(), u32::MAX as u64);
+
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(1), -1);
+        assert_eq!(zigzag(2), 1);
+        assert_eq!(zigzag(3), -2);
+        assert_eq!(zigzag(u64::MAX), i64::MIN);
+    }
+
+    #[test]
+    fn decodes_packed_fields() {
+        assert_eq!(packed_varints(&[0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05]).unwrap(), [3, 270, 86942]);
+        // Deltas +10, -3, +1 (zigzag 20, 5, 2)
+        assert_eq!(packed_deltas(&[0x14, 0x05, 0x02]).unwrap(), [10, 7, 8]);
+        assert!(packed_varints(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_fields_of_every_wire_type() {
+        // 1: varint 150, 2: bytes "ab", 3: fixed64, 4: fixed32
+        let data = [
+            0x08, 0x96, 0x01, 0x12, 0x02, b'a', b'b', 0x19, 0, 0, 0, 0, 0, 0, 0, 0, 0x25, 0, 0, 0, 0,
+        ];
+        let mut message = Message::new(&data);
+        assert!(matches!(message.next_field().unwrap(), Some((1, Value::Varint(150)))));
+        assert!(matches!(message.next_field().unwrap(), Some((2, Value::Bytes(b"ab")))));
+        assert!(matches!(message.next_field().unwrap(), Some((3, Value::Fixed))));
+        assert!(matches!(message.next_field().unwrap(), Some((4, Value::Fixed))));
+        assert!(message.next_field().unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let truncated: [&[u8]; 5] = [
+            // Varint with its continuation bit set on the last byte
+            &[0x08, 0x96],
+            // Bytes field announcing more than is left
+            &[0x12, 0x05, b'a'],
+            // Length that overflows the position
+            &[0x12, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            // Fixed64 and fixed32 cut short
+            &[0x19, 0, 0, 0],
+            &[0x25, 0, 0],
+        ];
+        for data in truncated {
+            let error = Message::new(data).next_field().err().expect("truncated input must fail");
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", data);
+        }
+        assert_eq!(packed_varints(&[0x80]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reads_map_mini() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../map_mini.pbf");
+        let (mut nodes, mut ways, mut relations) = (0, 0, 0);
+        read_elements(path, |element| match element {
+            Element::Node(_) => nodes += 1,
+            Element::Way(way) => {
+                assert!(way.refs.len() >= 2, "way {} has {} refs", way.id, way.refs.len());
+                ways += 1;
+            }
+            Element::Relation(_) => relations += 1,
+        })
+        .unwrap();
+        assert_eq!((nodes, ways, relations), (6199, 927, 19));
+    }
+}
@@ -0,0 +1,105 @@
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Postgres, QueryBuilder};
+use std::io;
+
+// Rows per INSERT statement, keeps each statement well under the Postgres bind limit
+const INSERT_CHUNK_SIZE: usize = 2000;
+
+/// One hourly count from the NYC Automated Traffic Volume Counts dataset,
+/// with its location already projected to WGS84.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct TrafficCount {
+    pub segment_id: i32,
+    pub year: i32,
+    pub month: i32,
+    pub day: i32,
+    pub hour: i32,
+    pub volume: i32,
+    pub boro: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub street: String,
+    pub from_street: String,
+    pub to_street: String,
+    pub direction: String,
+}
+
+pub async fn create_pool(database_url: &str) -> Result<sqlx::PgPool, io::Error> {
+    PgPoolOptions::new()
+        .max_connections(5)
+        .connect(database_url)
+        .await
+        .map_err(|err| io::Error::other(err.to_string())) // Convert sqlx::Error to io::Error
+}
+
+async fn execute(pool: &sqlx::PgPool, query: &str) -> Result<(), io::Error> {
+    sqlx::query(query)
+        .execute(pool)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    Ok(())
+}
+
+// traffic_counts replaces the untyped traffic_data table of import_traffic_data.sh and is
+// always rebuilt from scratch. lat / lon are WGS84 degrees like planet_osm_nodes.
+pub async fn create_tables(pool: &sqlx::PgPool) -> Result<(), io::Error> {
+    execute(pool, "DROP TABLE IF EXISTS traffic_counts;").await?;
+    execute(
+        pool,
+        r#"
+        CREATE TABLE traffic_counts (
+            segment_id INTEGER NOT NULL,
+            year INTEGER NOT NULL,
+            month INTEGER NOT NULL,
+            day INTEGER NOT NULL,
+            hour INTEGER NOT NULL,
+            volume INTEGER NOT NULL,
+            boro TEXT NOT NULL,
+            lat FLOAT8 NOT NULL,
+            lon FLOAT8 NOT NULL,
+            street TEXT NOT NULL,
+            from_street TEXT NOT NULL,
+            to_street TEXT NOT NULL,
+            direction TEXT NOT NULL
+        );
+    "#,
+    )
+    .await?;
+    execute(pool, "CREATE INDEX traffic_counts_segment_id ON traffic_counts (segment_id);").await?;
+
+    println!("Table traffic_counts ready.");
+    Ok(())
+}
+
+pub async fn insert_counts(pool: &sqlx::PgPool, counts: &[TrafficCount]) -> Result<(), io::Error> {
+    for chunk in counts.chunks(INSERT_CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO traffic_counts \
+             (segment_id, year, month, day, hour, volume, boro, lat, lon, street, from_street, to_street, direction) ",
+        );
+        builder.push_values(chunk, |mut row, count| {
+            row.push_bind(count.segment_id)
+                .push_bind(count.year)
+                .push_bind(count.month)
+                .push_bind(count.day)
+                .push_bind(count.hour)
+                .push_bind(count.volume)
+                .push_bind(count.boro.clone())
+                .push_bind(count.lat)
+                .push_bind(count.lon)
+                .push_bind(count.street.clone())
+                .push_bind(count.from_street.clone())
+                .push_bind(count.to_street.clone())
+                .push_bind(count.direction.clone());
+        });
+
+        builder
+            .build()
+            .execute(pool)
+            .await
+            .map_err(|err| io::Error::other(err.to_string()))?;
+    }
+
+    println!("Inserted {} rows into traffic_counts.", counts.len());
+    Ok(())
+}
@@ -0,0 +1,166 @@
+pub mod database;
+pub mod projection;
+
+use crate::database::TrafficCount;
+use crate::projection::{parse_wkt_point, LambertConformal};
+
+use dotenv::dotenv;
+use serde::Deserialize;
+use std::env;
+use std::io;
+use std::time::Instant;
+
+// Rough bounds of the five boroughs, projected points outside them mean bad input
+const NYC_LAT_RANGE: (f64, f64) = (40.45, 40.95);
+const NYC_LON_RANGE: (f64, f64) = (-74.30, -73.65);
+
+// Row of traffic_data.csv as exported from NYC Open Data, WktGeom is EPSG:2263
+#[derive(Debug, Deserialize)]
+struct CsvTrafficRow {
+    #[serde(rename = "SegmentID")]
+    segment_id: i32,
+    #[serde(rename = "M")]
+    month: i32,
+    #[serde(rename = "D")]
+    day: i32,
+    #[serde(rename = "HH")]
+    hour: i32,
+    #[serde(rename = "Yr")]
+    year: i32,
+    #[serde(rename = "Boro")]
+    boro: String,
+    #[serde(rename = "Vol")]
+    volume: i32,
+    #[serde(rename = "WktGeom")]
+    wkt_geom: String,
+    street: String,
+    #[serde(rename = "fromSt")]
+    from_street: String,
+    #[serde(rename = "toSt")]
+    to_street: String,
+    #[serde(rename = "Direction")]
+    direction: String,
+}
+
+#[derive(Debug, Default)]
+struct ImportStats {
+    rows: usize,
+    bad_geometry: usize,
+    outside_nyc: usize,
+    bad_time: usize,
+}
+
+pub fn load_config() -> String {
+    dotenv().ok();
+    env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+}
+
+fn in_nyc(lat: f64, lon: f64) -> bool {
+    (NYC_LAT_RANGE.0..=NYC_LAT_RANGE.1).contains(&lat) && (NYC_LON_RANGE.0..=NYC_LON_RANGE.1).contains(&lon)
+}
+
+fn read_counts(path: &str) -> io::Result<(Vec<TrafficCount>, ImportStats)> {
+    let projection = LambertConformal::new_york_long_island();
+    let mut stats = ImportStats::default();
+    let mut counts = Vec::new();
+
+    let mut reader = csv::Reader::from_path(path).map_err(io::Error::other)?;
+    for row in reader.deserialize() {
+        let row: CsvTrafficRow = row.map_err(io::Error::other)?;
+        stats.rows += 1;
+
+        let Some((easting, northing)) = parse_wkt_point(&row.wkt_geom) else {
+            stats.bad_geometry += 1;
+            continue;
+        };
+        let (lat, lon) = projection.inverse(easting, northing);
+        if !in_nyc(lat, lon) {
+            stats.outside_nyc += 1;
+            continue;
+        }
+        if !(0..24).contains(&row.hour) || !(1..=12).contains(&row.month) || !(1..=31).contains(&row.day) {
+            stats.bad_time += 1;
+            continue;
+        }
+
+        counts.push(TrafficCount {
+            segment_id: row.segment_id,
+            year: row.year,
+            month: row.month,
+            day: row.day,
+            hour: row.hour,
+            volume: row.volume,
+            boro: row.boro,
+            lat,
+            lon,
+            street: row.street,
+            from_street: row.from_street,
+            to_street: row.to_street,
+            direction: row.direction,
+        });
+    }
+
+    Ok((counts, stats))
+}
+
+fn write_csv(path: &str, counts: &[TrafficCount]) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path).map_err(io::Error::other)?;
+    for count in counts {
+        writer.serialize(count).map_err(io::Error::other)?;
+    }
+    writer.flush()?;
+    println!("Wrote {} rows to {}.", counts.len(), path);
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let csv_output = args
+        .iter()
+        .position(|arg| arg == "--csv")
+        .map(|position| {
+            args.get(position + 1)
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--csv expects an output path"))
+        })
+        .transpose()?;
+    let traffic_file_path = args
+        .iter()
+        .enumerate()
+        .find(|(i, arg)| !arg.starts_with("--") && (*i == 0 || args[i - 1] != "--csv"))
+        .map(|(_, arg)| arg.clone())
+        .unwrap_or_else(|| "traffic_data.csv".to_string());
+
+    let parse_start = Instant::now();
+    let (counts, stats) = read_counts(&traffic_file_path)?;
+    println!("Finished reading file: {} in {:?}", traffic_file_path, parse_start.elapsed());
+
+    println!("Number of rows: {} ({} imported)", stats.rows, counts.len());
+    println!(
+        "Skipped rows: {} bad geometry, {} outside NYC, {} bad date or hour",
+        stats.bad_geometry, stats.outside_nyc, stats.bad_time
+    );
+
+    if let Some(path) = &csv_output {
+        write_csv(path, &counts)?;
+    }
+
+    if dry_run {
+        println!("Dry run, nothing written to the database.");
+        return Ok(());
+    }
+
+    // Load database configuration
+    let config = load_config();
+
+    // Create a connection pool
+    let pool = database::create_pool(&config).await?;
+
+    database::create_tables(&pool).await?;
+    database::insert_counts(&pool, &counts).await?;
+
+    drop(pool); // This will close all connections in the pool
+    Ok(())
+}
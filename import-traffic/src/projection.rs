@@ -0,0 +1,232 @@
+// Map projections needed to bring source data into WGS84 lat/lon.
+//
+// NAD83 and WGS84 differ by about a meter, well below the size of a street,
+// so NAD83 coordinates are used as WGS84 without a datum shift.
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+// One US survey foot in meters, the unit of the New York State Plane systems
+pub const US_SURVEY_FOOT: f64 = 1200.0 / 3937.0;
+
+// Latitude iteration stops once a step moves less than this (radians, ~0.6 mm)
+const INVERSE_TOLERANCE: f64 = 1e-10;
+const INVERSE_MAX_ITERATIONS: usize = 15;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ellipsoid {
+    pub semi_major_axis: f64,
+    pub inverse_flattening: f64,
+}
+
+impl Ellipsoid {
+    pub const GRS80: Ellipsoid = Ellipsoid {
+        semi_major_axis: 6_378_137.0,
+        inverse_flattening: 298.257_222_101,
+    };
+
+    pub const CLARKE_1866: Ellipsoid = Ellipsoid {
+        semi_major_axis: 6_378_206.4,
+        inverse_flattening: 294.978_698_2,
+    };
+
+    fn eccentricity(&self) -> f64 {
+        let flattening = 1.0 / self.inverse_flattening;
+        (2.0 * flattening - flattening * flattening).sqrt()
+    }
+}
+
+/// Lambert Conformal Conic with two standard parallels (EPSG method 9802).
+///
+/// Angles are given in degrees, false easting / northing and results in `unit`
+/// (meters per projected unit).
+#[derive(Clone, Copy, Debug)]
+pub struct LambertConformal {
+    semi_major_axis: f64,
+    eccentricity: f64,
+    central_meridian: f64,
+    false_easting: f64,
+    false_northing: f64,
+    unit: f64,
+    n: f64,
+    f: f64,
+    origin_radius: f64,
+}
+
+impl LambertConformal {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        ellipsoid: Ellipsoid,
+        origin_latitude: f64,
+        central_meridian: f64,
+        first_parallel: f64,
+        second_parallel: f64,
+        false_easting: f64,
+        false_northing: f64,
+        unit: f64,
+    ) -> LambertConformal {
+        let eccentricity = ellipsoid.eccentricity();
+        let (phi_1, phi_2) = (first_parallel.to_radians(), second_parallel.to_radians());
+        let (m_1, m_2) = (m(phi_1, eccentricity), m(phi_2, eccentricity));
+        let (t_1, t_2) = (t(phi_1, eccentricity), t(phi_2, eccentricity));
+
+        let n = (m_1.ln() - m_2.ln()) / (t_1.ln() - t_2.ln());
+        let f = m_1 / (n * t_1.powf(n));
+        let origin_radius = ellipsoid.semi_major_axis * f * t(origin_latitude.to_radians(), eccentricity).powf(n);
+
+        LambertConformal {
+            semi_major_axis: ellipsoid.semi_major_axis,
+            eccentricity,
+            central_meridian: central_meridian.to_radians(),
+            false_easting: false_easting * unit,
+            false_northing: false_northing * unit,
+            unit,
+            n,
+            f,
+            origin_radius,
+        }
+    }
+
+    /// EPSG:2263, NAD83 / New York Long Island (ftUS), used by NYC open data.
+    pub fn new_york_long_island() -> LambertConformal {
+        LambertConformal::new(
+            Ellipsoid::GRS80,
+            40.0 + 10.0 / 60.0,
+            -74.0,
+            41.0 + 2.0 / 60.0,
+            40.0 + 40.0 / 60.0,
+            984_250.0,
+            0.0,
+            US_SURVEY_FOOT,
+        )
+    }
+
+    /// Project a latitude / longitude in degrees to (easting, northing).
+    pub fn forward(&self, latitude: f64, longitude: f64) -> (f64, f64) {
+        let radius = self.semi_major_axis * self.f * t(latitude.to_radians(), self.eccentricity).powf(self.n);
+        let theta = self.n * (longitude.to_radians() - self.central_meridian);
+
+        let easting = self.false_easting + radius * theta.sin();
+        let northing = self.false_northing + self.origin_radius - radius * theta.cos();
+        (easting / self.unit, northing / self.unit)
+    }
+
+    /// Back to (latitude, longitude) in degrees from an easting / northing.
+    pub fn inverse(&self, easting: f64, northing: f64) -> (f64, f64) {
+        let x = easting * self.unit - self.false_easting;
+        let y = self.origin_radius - (northing * self.unit - self.false_northing);
+        let sign = self.n.signum();
+
+        let radius = sign * (x * x + y * y).sqrt();
+        let t = (radius / (self.semi_major_axis * self.f)).powf(1.0 / self.n);
+        let theta = (sign * x).atan2(sign * y);
+
+        // Latitude has no closed form, iterate from the spherical solution
+        let e = self.eccentricity;
+        let mut latitude = FRAC_PI_2 - 2.0 * t.atan();
+        for _ in 0..INVERSE_MAX_ITERATIONS {
+            let e_sin = e * latitude.sin();
+            let next = FRAC_PI_2 - 2.0 * (t * ((1.0 - e_sin) / (1.0 + e_sin)).powf(e / 2.0)).atan();
+            let step = (next - latitude).abs();
+            latitude = next;
+            if step < INVERSE_TOLERANCE {
+                break;
+            }
+        }
+
+        let longitude = theta / self.n + self.central_meridian;
+        (latitude.to_degrees(), longitude.to_degrees())
+    }
+}
+
+fn m(phi: f64, e: f64) -> f64 {
+    phi.cos() / (1.0 - (e * phi.sin()).powi(2)).sqrt()
+}
+
+fn t(phi: f64, e: f64) -> f64 {
+    let e_sin = e * phi.sin();
+    (FRAC_PI_4 - phi / 2.0).tan() / ((1.0 - e_sin) / (1.0 + e_sin)).powf(e / 2.0)
+}
+
+/// Coordinates of a WKT `POINT (x y)`, None for anything else.
+pub fn parse_wkt_point(wkt: &str) -> Option<(f64, f64)> {
+    let inner = wkt
+        .trim()
+        .strip_prefix("POINT")?
+        .trim()
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    let mut parts = inner.split_whitespace();
+    let x = parts.next()?.parse::<f64>().ok()?;
+    let y = parts.next()?.parse::<f64>().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NAD27 / Texas South Central, the worked example of EPSG Guidance Note 7-2
+    fn texas_south_central() -> LambertConformal {
+        LambertConformal::new(
+            Ellipsoid::CLARKE_1866,
+            27.0 + 50.0 / 60.0,
+            -99.0,
+            28.0 + 23.0 / 60.0,
+            30.0 + 17.0 / 60.0,
+            2_000_000.0,
+            0.0,
+            US_SURVEY_FOOT,
+        )
+    }
+
+    #[test]
+    fn epsg_example_forward() {
+        // 28°30'N 96°W -> 2963503.91 ftUS E, 254759.80 ftUS N
+        let (easting, northing) = texas_south_central().forward(28.5, -96.0);
+        assert!((easting - 2_963_503.91).abs() < 0.05, "easting {:.2}", easting);
+        assert!((northing - 254_759.80).abs() < 0.05, "northing {:.2}", northing);
+    }
+
+    #[test]
+    fn epsg_example_inverse() {
+        let (lat, lon) = texas_south_central().inverse(2_963_503.91, 254_759.80);
+        assert!((lat - 28.5).abs() < 1e-7, "latitude {:.9}", lat);
+        assert!((lon + 96.0).abs() < 1e-7, "longitude {:.9}", lon);
+    }
+
+    #[test]
+    fn long_island_origin_maps_to_false_easting() {
+        let (easting, northing) = LambertConformal::new_york_long_island().forward(40.0 + 10.0 / 60.0, -74.0);
+        assert!((easting - 984_250.0).abs() < 1e-6, "easting {:.6}", easting);
+        assert!(northing.abs() < 1e-6, "northing {:.6}", northing);
+    }
+
+    #[test]
+    fn manhattan_round_trips() {
+        // From the Battery to Inwood, back within a millimeter
+        let long_island = LambertConformal::new_york_long_island();
+        for i in 0..=20 {
+            let (lat, lon) = (40.70 + 0.009 * i as f64, -74.02 + 0.005 * i as f64);
+            let (easting, northing) = long_island.forward(lat, lon);
+            let (back_lat, back_lon) = long_island.inverse(easting, northing);
+            assert!(
+                (back_lat - lat).abs() < 1e-8 && (back_lon - lon).abs() < 1e-8,
+                "{}, {} came back as {}, {}",
+                lat,
+                lon,
+                back_lat,
+                back_lon
+            );
+        }
+    }
+
+    #[test]
+    fn wkt_points() {
+        assert_eq!(parse_wkt_point("POINT (987654.3 204567.8)"), Some((987654.3, 204567.8)));
+        assert_eq!(parse_wkt_point("POINT (1 2 3)"), None);
+        assert_eq!(parse_wkt_point("LINESTRING (1 2, 3 4)"), None);
+    }
+}
@@ -1,5 +1,8 @@
 #!/bin/bash
 
+# Loads the raw CSV as-is. The import-traffic crate builds the typed traffic_counts
+# table with WGS84 coordinates that the router reads.
+
 # Database connection details
 DB_NAME="osm_manhattan"
 DB_USER="postgres"
@@ -32,7 +35,7 @@ CREATE TABLE $TABLE_NAME (
     fromst TEXT,
     tost TEXT,
     direction TEXT,
-    geom GEOMETRY(POINT, 3875)  -- Store geometry in EPSG:3875
+    geom GEOMETRY(POINT, 3857)  -- Store geometry in EPSG:3857
 );
 "
 
@@ -43,11 +46,11 @@ psql -h $DB_HOST -p $DB_PORT -U $DB_USER -d $DB_NAME -c "\
 FROM '$CSV_FILE'
 DELIMITER ',' CSV HEADER;"
 
-# Convert WKT to geometry and transform to EPSG:3875
-echo "Converting WKT to geometry and transforming to EPSG:3875..."
+# Convert WKT to geometry and transform to EPSG:3857
+echo "Converting WKT to geometry and transforming to EPSG:3857..."
 psql -h $DB_HOST -p $DB_PORT -U $DB_USER -d $DB_NAME -c "
 UPDATE $TABLE_NAME
-SET geom = ST_Transform(ST_SetSRID(ST_GeomFromText(wktgeom), 2263), 3875)
+SET geom = ST_Transform(ST_SetSRID(ST_GeomFromText(wktgeom), 2263), 3857)
 WHERE wktgeom IS NOT NULL;
 "
 
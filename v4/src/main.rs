@@ -5,6 +5,10 @@ use std::io::{self};
 use dotenv::dotenv;
 use std::env;
 
+use get_shortest_path::algorithm::Algorithm;
+use get_shortest_path::mode::TravelMode;
+use get_shortest_path::source::{self, GraphSource};
+
 pub fn load_config() -> String {
     dotenv().ok();
     env::var("DATABASE_URL").expect("DATABASE_URL must be set")
@@ -143,6 +147,24 @@ async fn test_get_shortest_path_multiple(pool: &sqlx::PgPool) {
 
 }
 
+// Same route as test_get_shortest_path_multiple, through the routing crate over the configured backend
+async fn test_get_shortest_path_source<S: GraphSource>(source: &S) {
+    let points = vec![
+        (40.351712, -74.663318), // 103994771
+        (40.351305, -74.6633467), // 8942477433
+        (40.35054, -74.6630122), // 104105303
+    ];
+
+    match get_shortest_path::get_shortest_path_multiple(source, Algorithm::default(), TravelMode::default(), points).await {
+        Ok(route) => {
+            println!("Shortest path: {:?}", route.path);
+        }
+        Err(e) => {
+            eprintln!("Error getting shortest path: {}", e);
+        }
+    }
+}
+
 async fn get_shortest_path(pool: &sqlx::PgPool, start_lat: f64, start_lon: f64, end_lat: f64, end_lon: f64) ->  Result<Vec<[f64; 2]>, io::Error> {
     // Fetch the source node
     let src_node = match database::get_node_by_lat_lon(pool, start_lat, start_lon).await {
@@ -212,6 +234,17 @@ async fn get_shortest_path_multiple(
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
+    dotenv().ok();
+
+    // Pick the graph backend from GRAPH_BACKEND (postgres, memory or csv)
+    let source = source::load_source().await?;
+    test_get_shortest_path_source(&source).await;
+
+    // The checks below go straight to Postgres through v4's own database module
+    if env::var("DATABASE_URL").is_err() {
+        return Ok(());
+    }
+
     // Load database configuration
     let config = load_config();
 